@@ -0,0 +1,325 @@
+//! Music, sound effects and the audio output.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{starting_run, GameMode, GameState},
+    physics::TimeScale,
+    settings::{percent_string, Settings},
+};
+
+/// Music and the audio settings.
+pub(crate) struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SoundEvent>()
+            .init_resource::<AudioOutputs>()
+            .add_startup_system(check_audio_output)
+            .add_system(
+                change_music
+                    .run_if(starting_run)
+                    .in_schedule(OnEnter(GameState::Running)),
+            )
+            .add_system(change_music.in_schedule(OnEnter(GameState::Ended)))
+            .add_system(music_mute_system)
+            .add_system(update_music_volume.after(music_mute_system))
+            .add_system(volume_meter_system)
+            .add_system(heartbeat_system.run_if(in_state(GameState::Running)))
+            .add_system(play_sound_effects.after(heartbeat_system));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct AudioSettings {
+    /// Volume of the background music, between 0 and 1.
+    pub(crate) music_volume: f32,
+    /// Volume of the sound effects, between 0 and 1.
+    pub(crate) sfx_volume: f32,
+    /// Show a small meter with the audio levels, e.g. for streaming.
+    pub(crate) show_meter: bool,
+    /// Stop the background music while keeping the sound effects. Toggled with N.
+    pub(crate) music_muted: bool,
+    /// Name of the preferred output device. None uses the system default.
+    pub(crate) output_device: Option<String>,
+}
+
+impl AudioSettings {
+    /// Fall back to the default output if the preferred device is gone.
+    ///
+    /// Bevy 0.10's audio plugin always opens the default output through
+    /// `rodio::OutputStream::try_default` and doesn't let us pass a device, so the preference
+    /// is only recorded for now. Once the audio output can be constructed from a
+    /// `rodio::cpal::Device`, look it up by name in [`AudioOutputs`] here.
+    pub(crate) fn check_output_device(&mut self, outputs: &AudioOutputs) {
+        let Some(name) = &self.output_device else {
+            return;
+        };
+        if outputs.0.contains(name) {
+            info!(
+                "Preferred audio output {} found, but the default output is used",
+                name
+            );
+        } else {
+            warn!("Audio output {} not found, using the default output", name);
+            self.output_device = None;
+        }
+    }
+}
+
+/// Names of the audio output devices, listed once at startup since that can be slow.
+#[derive(Resource, Default)]
+pub(crate) struct AudioOutputs(pub(crate) Vec<String>);
+
+fn check_audio_output(mut settings: ResMut<Settings>, mut outputs: ResMut<AudioOutputs>) {
+    outputs.0 = audio_output_devices();
+    settings.audio.check_output_device(&outputs);
+}
+
+/// Names of the available audio output devices.
+#[cfg(not(target_arch = "wasm32"))]
+fn audio_output_devices() -> Vec<String> {
+    use rodio::cpal::traits::{DeviceTrait, HostTrait};
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(err) => {
+            warn!("Failed to list the audio outputs: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// The browser picks the output device.
+#[cfg(target_arch = "wasm32")]
+fn audio_output_devices() -> Vec<String> {
+    Vec::new()
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            show_meter: false,
+            music_muted: false,
+            output_device: None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct VolumeMeterText;
+
+#[derive(Resource)]
+pub(crate) struct MusicResource {
+    pub(crate) game_source: Handle<AudioSource>,
+    /// Game music of the modes that don't use the default track.
+    pub(crate) mode_sources: HashMap<GameMode, Handle<AudioSource>>,
+    pub(crate) over_source: Handle<AudioSource>,
+    pub(crate) current: Option<Handle<AudioSink>>,
+}
+
+/// Short one-shot sounds, played at the sound effect volume.
+#[derive(Resource)]
+pub(crate) struct SoundEffects {
+    pub(crate) shoot: Handle<AudioSource>,
+    /// Positive chime for destroying a germ.
+    pub(crate) germ_killed: Handle<AudioSource>,
+    /// Negative thud for losing a body cell or letting a germ through.
+    pub(crate) cell_lost: Handle<AudioSource>,
+    /// Beats during the last breath slow motion.
+    pub(crate) heartbeat: Handle<AudioSource>,
+}
+
+/// Request to play a sound effect.
+#[derive(Clone, Copy)]
+pub(crate) enum SoundEvent {
+    Shoot,
+    GermKilled,
+    CellLost,
+    Heartbeat,
+}
+
+pub(crate) fn change_music(
+    mut music_res: ResMut<MusicResource>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    game_state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    game_mode: Res<GameMode>,
+) {
+    stop_music(&mut music_res, &audio_sinks);
+    play_music(
+        &mut music_res,
+        &audio,
+        &audio_sinks,
+        game_state.0,
+        *game_mode,
+        &settings.audio,
+    );
+}
+
+fn play_sound_effects(
+    mut events: EventReader<SoundEvent>,
+    sound_effects: Res<SoundEffects>,
+    settings: Res<Settings>,
+    audio: Res<Audio>,
+    sources: Res<Assets<AudioSource>>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            SoundEvent::Shoot => &sound_effects.shoot,
+            SoundEvent::GermKilled => &sound_effects.germ_killed,
+            SoundEvent::CellLost => &sound_effects.cell_lost,
+            SoundEvent::Heartbeat => &sound_effects.heartbeat,
+        };
+        // Audio keeps sources that haven't loaded queued, skip them instead of piling up shots.
+        if settings.audio.sfx_volume <= 0.0 || !sources.contains(handle) {
+            continue;
+        }
+        audio.play_with_settings(
+            handle.clone(),
+            PlaybackSettings::ONCE.with_volume(settings.audio.sfx_volume),
+        );
+    }
+}
+
+pub(crate) fn stop_music(music_res: &mut MusicResource, audio_sinks: &Assets<AudioSink>) {
+    if let Some(music) = music_res.current.take() {
+        if let Some(music) = audio_sinks.get(&music) {
+            music.stop();
+        }
+    }
+}
+
+/// Start the music of the given state, unless the music is muted.
+fn play_music(
+    music_res: &mut MusicResource,
+    audio: &Audio,
+    audio_sinks: &Assets<AudioSink>,
+    game_state: GameState,
+    game_mode: GameMode,
+    audio_settings: &AudioSettings,
+) {
+    if audio_settings.music_muted {
+        return;
+    }
+    let new_music = match game_state {
+        GameState::Running | GameState::Paused => music_res
+            .mode_sources
+            .get(&game_mode)
+            .unwrap_or(&music_res.game_source)
+            .clone(),
+        GameState::Ended => music_res.over_source.clone(),
+        _ => {
+            return;
+        }
+    };
+    let sink = audio.play_with_settings(
+        new_music,
+        PlaybackSettings::LOOP.with_volume(audio_settings.music_volume),
+    );
+    let sink = audio_sinks.get_handle(sink);
+    music_res.current = Some(sink);
+}
+
+fn music_mute_system(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::N) {
+        settings.audio.music_muted = !settings.audio.music_muted;
+        settings.save();
+    }
+}
+
+/// Apply volume changes and muting to the music that is already playing.
+fn update_music_volume(
+    settings: Res<Settings>,
+    mut music_res: ResMut<MusicResource>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    game_state: Res<State<GameState>>,
+    game_mode: Res<GameMode>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    if settings.audio.music_muted {
+        stop_music(&mut music_res, &audio_sinks);
+    } else if music_res.current.is_none() {
+        play_music(
+            &mut music_res,
+            &audio,
+            &audio_sinks,
+            game_state.0,
+            *game_mode,
+            &settings.audio,
+        );
+    } else if let Some(sink) = music_res
+        .current
+        .as_ref()
+        .and_then(|sink| audio_sinks.get(sink))
+    {
+        sink.set_volume(settings.audio.music_volume);
+    }
+}
+
+/// Draw a level between 0 and 1 as a bar of 10 segments.
+pub(crate) fn level_bar(level: f32) -> String {
+    let filled = (level * 10.0).round().clamp(0.0, 10.0) as usize;
+    format!("{}{}", "|".repeat(filled), ".".repeat(10 - filled))
+}
+
+fn volume_meter_system(
+    settings: Res<Settings>,
+    music_res: Res<MusicResource>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut query: Query<&mut Text, With<VolumeMeterText>>,
+) {
+    let value = if settings.audio.show_meter {
+        // Prefer the actual volume of the playing music over the setting.
+        let music_volume = match music_res
+            .current
+            .as_ref()
+            .and_then(|sink| audio_sinks.get(sink))
+        {
+            Some(sink) if sink.is_paused() => 0.0,
+            Some(sink) => sink.volume(),
+            None => 0.0,
+        };
+        format!(
+            "MUSIC {} {}\nSFX   {} {}",
+            level_bar(music_volume),
+            percent_string(music_volume),
+            level_bar(settings.audio.sfx_volume),
+            percent_string(settings.audio.sfx_volume),
+        )
+    } else {
+        String::new()
+    };
+    for mut text in &mut query {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+/// Beat along with the last breath overlay. Unlike the overlay, this is kept with reduce motion.
+fn heartbeat_system(
+    time: Res<Time>,
+    time_scale: Res<TimeScale>,
+    mut last_beat: Local<Option<u64>>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    if !time_scale.in_last_breath() {
+        *last_beat = None;
+        return;
+    }
+    let beat = time.raw_elapsed_seconds_f64() as u64;
+    if *last_beat != Some(beat) {
+        *last_beat = Some(beat);
+        sound_events.send(SoundEvent::Heartbeat);
+    }
+}
@@ -0,0 +1,127 @@
+//! Components shared by the gameplay, physics and UI systems.
+
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub(crate) struct Cell {
+    pub(crate) target_radius: f32,
+    pub(crate) cell_type: CellType,
+    pub(crate) top_bound: f32,
+    /// Radius the cell spawned with. The remaining health is measured against it.
+    pub(crate) full_radius: f32,
+    /// Drawn scale of the sprite. Eases towards the collision radius so that the cell doesn't
+    /// visibly pop when it's damaged.
+    pub(crate) visual_scale: f32,
+    /// Seconds of on-screen time left before bullets can damage the cell.
+    pub(crate) spawn_protection: f32,
+    /// Number of splits the cell descends from, see [`CellType::SplitterGerm`].
+    pub(crate) generation: u8,
+}
+
+impl Cell {
+    /// Remaining fraction of the cell's spawn size.
+    pub(crate) fn health(&self) -> f32 {
+        (self.target_radius / self.full_radius).clamp(0.0, 1.0)
+    }
+}
+
+pub(crate) enum CellType {
+    /// This is a cell belonging to the patient's body.
+    Body {
+        /// How much hp the patient will lose when this cell is destroyed.
+        patient_hp: i32,
+    },
+    /// Enemy cell
+    Germ,
+    /// Germ that splits into two smaller ones when destroyed, up to
+    /// [`crate::spawner::SPLITTER_MAX_GENERATION`].
+    SplitterGerm,
+    /// Germ that periodically shoots at the player.
+    ShooterGerm { shoot_timer: Timer },
+    /// Germ whose armor has to be broken before bullets can damage it.
+    ArmoredGerm {
+        /// Bullet hits the armor can still absorb.
+        hits_remaining: u8,
+    },
+}
+
+impl CellType {
+    pub(crate) fn is_germ(&self) -> bool {
+        matches!(
+            self,
+            CellType::Germ
+                | CellType::SplitterGerm
+                | CellType::ShooterGerm { .. }
+                | CellType::ArmoredGerm { .. }
+        )
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct Physics {
+    pub(crate) velocity: Vec2,
+    pub(crate) acceleration: Vec2,
+    pub(crate) elasticity: f32,
+    pub(crate) radius: f32,
+}
+
+impl Physics {
+    /// Assume densities are the same: mass is proportional to the area of the circle.
+    pub(crate) fn mass(&self) -> f32 {
+        self.radius * self.radius
+    }
+
+    /// Changes the velocity by the given impulse, so heavier objects are affected less.
+    pub(crate) fn apply_impulse(&mut self, impulse: Vec2) {
+        self.velocity += impulse / self.mass();
+    }
+}
+
+#[cfg(test)]
+impl Cell {
+    /// Unharmed cell of the given size.
+    pub(crate) fn new(cell_type: CellType, radius: f32) -> Self {
+        Self {
+            target_radius: radius,
+            cell_type,
+            top_bound: 0.0,
+            full_radius: radius,
+            visual_scale: 1.0,
+            spawn_protection: 0.0,
+            generation: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Physics {
+    /// Body of the given size at rest, bouncing like a cell.
+    pub(crate) fn new(radius: f32) -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            acceleration: Vec2::ZERO,
+            elasticity: 0.9,
+            radius,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::vec2;
+
+    use super::*;
+    use crate::config::GameConfig;
+
+    #[test]
+    fn knockback_moves_large_cells_less() {
+        let impulse = vec2(0.0, GameConfig::default().knockback_impulse);
+        let mut small = Physics::new(20.0);
+        let mut large = Physics::new(60.0);
+        small.apply_impulse(impulse);
+        large.apply_impulse(impulse);
+        assert!(large.velocity.y > 0.0);
+        // Mass grows with the area, so three times the radius takes nine times the impulse.
+        assert!((small.velocity.y / large.velocity.y - 9.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,363 @@
+//! Tuning values loaded from the config file, and saving and loading RON files.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    game::GameMode,
+    side_effects::MissedShotRisk,
+    spawner::{FormationOrder, CELL_TEXTURE_RADIUS, MAX_CELL_GRAVITY},
+};
+
+/// Gameplay tuning values. Can be overridden with a `config.ron` file in the save directory.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct GameConfig {
+    /// Fraction of the horizontal velocity kept when the player bounces off a wall.
+    /// 0 stops the player at the wall.
+    pub(crate) player_wall_elasticity: f32,
+    /// Average number of infection hazards left behind by each germ per second.
+    pub(crate) hazard_spawn_rate: f64,
+    /// Seconds before a hazard fades away.
+    pub(crate) hazard_lifetime: f32,
+    pub(crate) hazard_radius: f32,
+    /// Damage dealt to a body cell passing through a hazard.
+    pub(crate) hazard_cell_damage: f32,
+    /// Side-effect risk added when the player passes through a hazard.
+    pub(crate) hazard_player_risk: i32,
+    /// Fraction of the germs spawned as armored germs.
+    pub(crate) armored_germ_chance: f64,
+    /// Bullet hits an armored germ absorbs before taking damage.
+    pub(crate) armored_germ_hits: u8,
+    /// Fraction of the germs spawned as splitter germs.
+    pub(crate) splitter_germ_chance: f64,
+    /// Sideways speed of the halves of a splitter germ.
+    pub(crate) splitter_speed: f32,
+    /// Fraction of the germs spawned as shooter germs.
+    pub(crate) shooter_germ_chance: f64,
+    /// Seconds between the shots of a shooter germ.
+    pub(crate) shooter_germ_interval: f32,
+    pub(crate) enemy_bullet_speed: f32,
+    pub(crate) enemy_bullet_radius: f32,
+    /// Patient hp lost when an enemy bullet hits the player.
+    pub(crate) enemy_bullet_damage: i32,
+    /// Chance of a cure pickup coming with each wave. Collecting it clears the side effect the
+    /// player is in, or reduces the risk of the player's side if there's none.
+    pub(crate) cure_chance: f64,
+    pub(crate) cure_radius: f32,
+    /// Risk removed by a cure collected where no side effect is active.
+    pub(crate) cure_risk_reduction: i32,
+    /// Chance of a health pickup coming with each wave.
+    pub(crate) health_pickup_chance: f64,
+    pub(crate) health_pickup_radius: f32,
+    /// Patient hp restored by a health pickup.
+    pub(crate) health_pickup_amount: i32,
+    /// Width of the side-effect zones as a fraction of the distance from the center to the wall.
+    pub(crate) left_zone_width: f32,
+    pub(crate) right_zone_width: f32,
+    pub(crate) missed_shot_risk: MissedShotRisk,
+    /// Side-effect risk added by each missed shot.
+    pub(crate) missed_shot_effect_risk: i32,
+    /// Default duration of the side effects, see [`SideEffectType::duration`].
+    pub(crate) side_effect_duration: f32,
+    /// Side-effect risk can't accumulate beyond this, so that missed shots can't cause an
+    /// endless chain of side effects.
+    pub(crate) max_effect_risk: i32,
+    /// Energy consumed by each shot. 0 makes shooting free.
+    pub(crate) shot_energy_cost: f32,
+    /// Energy regenerated per second.
+    pub(crate) energy_regen_rate: f32,
+    pub(crate) max_energy: f32,
+    /// Heat added by each shot. Reaching a heat of 1 overheats the weapon. 0 disables heat.
+    pub(crate) shot_heat: f32,
+    /// Heat lost per second.
+    pub(crate) heat_cooling_rate: f32,
+    /// An overheated weapon can fire again once its heat drops below this.
+    pub(crate) overheat_recovery: f32,
+    /// Patient health at the start of a run, which can't be exceeded by healing.
+    pub(crate) max_patient_hp: i32,
+    /// Length of a time attack run in seconds.
+    pub(crate) time_attack_duration: f32,
+    /// The time attack countdown turns red when this many seconds are left.
+    pub(crate) time_attack_warning: f32,
+    /// Radius lost by a cell hit by a normal bullet.
+    pub(crate) bullet_damage: f32,
+    /// Radius lost by both cells when two cells with different types collide.
+    pub(crate) cell_intercollision_damage: f32,
+    /// Radius lost by a cell touched by the player in a no shooting zone.
+    pub(crate) player_collision_damage: f32,
+    /// Scroll speed of the vein walls, before the bloodstream speed.
+    pub(crate) background_scroll_speed: f32,
+    /// Scroll speed of the vein background relative to the walls. It's further away, so it
+    /// scrolls slower.
+    pub(crate) background_parallax: f32,
+    /// Upward impulse given to a cell hit by a bullet. Divided by the cell's mass,
+    /// so large cells barely move while small ones are knocked away.
+    pub(crate) knockback_impulse: f32,
+    /// Number of bullets spawned up front and reused, see [`BulletPool`](crate::player::BulletPool).
+    pub(crate) bullet_pool_size: usize,
+    /// Downward speed added to the player by each shot in a recoil shots zone.
+    pub(crate) recoil_speed: f32,
+    /// Distance the player has to move past a side-effect zone boundary to leave the zone.
+    pub(crate) zone_exit_margin: f32,
+    /// Range of the random vertical gap between the cells of a wave.
+    pub(crate) spawn_gap_min: f32,
+    pub(crate) spawn_gap_max: f32,
+    /// Formation of the waves.
+    pub(crate) formation_order: FormationOrder,
+    /// Range of the random downward acceleration of the spawned cells, before the bloodstream
+    /// speed. Capped at [`MAX_CELL_GRAVITY`]. Each cell of a scattered wave draws its own, while
+    /// the cells of a formation share one to keep its shape. Set `formation_order` to
+    /// `ScatterOnly` to vary it for every cell.
+    pub(crate) cell_gravity_min: f32,
+    pub(crate) cell_gravity_max: f32,
+    /// Patient health restored by killing a germ. 0 disables healing on kills.
+    pub(crate) germ_kill_heal: i32,
+    /// Score for destroying a germ, before the run modifiers.
+    pub(crate) germ_kill_score: usize,
+    /// Seconds between germ kills for them to count as a combo.
+    pub(crate) combo_window: f32,
+    /// Patient hp lost when a body cell is destroyed, by bullets or by touching.
+    pub(crate) body_kill_damage: i32,
+    /// Patient hp recovered when a full health body cell passes the bottom. Damaged cells
+    /// recover proportionally less.
+    pub(crate) body_exit_heal: i32,
+    /// Patient hp lost when a germ escapes from the bottom.
+    pub(crate) germ_exit_damage: i32,
+    /// Patient hp lost when a boss escapes from the bottom.
+    pub(crate) boss_exit_damage: i32,
+    /// Speed of the bloodstream at the start of a run, which scales both the scrolling and the
+    /// falling speed of the cells. See [`GameConfig::bloodstream_speed`].
+    pub(crate) bloodstream_speed_base: f32,
+    /// Increase of the bloodstream speed per scored point.
+    pub(crate) bloodstream_speed_per_point: f32,
+    pub(crate) bloodstream_speed_max: f32,
+    /// Distance between the top edge of the playfield and the highest point the player can reach.
+    pub(crate) player_top_margin: f32,
+    /// Patient hp below which the game slows down for the last breath.
+    pub(crate) last_breath_hp: i32,
+    /// Game speed during the last breath.
+    pub(crate) last_breath_speed: f32,
+    /// Speed of the normal bullets. The other bullet kinds scale it.
+    pub(crate) bullet_speed: f32,
+    /// Seconds the fire key has to be held for a charged shot, if enabled in the settings.
+    pub(crate) charge_min_time: f32,
+    /// Seconds of holding until the shot is fully charged. A full charge pierces one cell.
+    pub(crate) charge_full_time: f32,
+    /// Size and damage of a fully charged shot relative to a normal bullet.
+    pub(crate) charge_max_power: f32,
+    /// Score needed for each additional cell the bullets pierce. 0 disables piercing.
+    pub(crate) pierce_score_interval: usize,
+    /// Degrees between the bullets of a spread shot.
+    pub(crate) spread_angle: f32,
+    /// Cells falling faster than this get an intercept indicator, if enabled in the settings.
+    pub(crate) intercept_indicator_min_speed: f32,
+    /// Seconds the player can stay pinned against the top edge before shooting slows down.
+    /// 0 disables the penalty.
+    pub(crate) top_camping_time: f32,
+    /// Factor by which the fire rate is reduced while camping at the top edge.
+    pub(crate) top_camping_fire_slowdown: f32,
+    /// The movement multiplier of the side effect zones (e.g. slow motion) applies to the cells
+    /// inside them as well, not only to the player. The other effects only concern the player.
+    pub(crate) zone_movement_affects_cells: bool,
+    /// Cells stop at the side walls instead of bouncing off. They still bounce off each other.
+    pub(crate) cells_stop_at_walls: bool,
+    /// Fraction of the overlap by which each of two colliding bodies is pushed back.
+    /// 0.5 resolves the overlap exactly, larger values push them further apart.
+    pub(crate) collision_push_factor: f32,
+    /// Number of passes resolving the overlaps between cells in each physics step.
+    pub(crate) collision_iterations: u32,
+    /// Seconds a marker shows where a cell will enter before it starts moving. 0 disables it.
+    pub(crate) spawn_telegraph: f32,
+    /// Seconds a cell has to be on screen before bullets can damage it. 0 disables protection.
+    pub(crate) spawn_protection: f32,
+    /// Cells of the same type touching slower than this may merge into a larger one.
+    pub(crate) cell_merge_max_speed: f32,
+    /// Chance that two slow touching cells merge, rolled once when they start touching.
+    pub(crate) cell_merge_chance: f64,
+    /// Cells never merge into one larger than this.
+    pub(crate) cell_merge_max_radius: f32,
+    /// Asset paths of the game music for modes that don't use the default track.
+    pub(crate) mode_music: HashMap<GameMode, String>,
+    /// Seconds before the restart key works on the game over screen.
+    pub(crate) restart_cooldown: f32,
+    /// Score between boss fights. 0 disables bosses.
+    pub(crate) boss_score_interval: usize,
+    /// Seconds the boss warning is shown before the boss enters.
+    pub(crate) boss_warning_time: f32,
+    /// Spawn protection of the boss, see [`GameConfig::spawn_protection`].
+    pub(crate) boss_intro_protection: f32,
+    pub(crate) boss_radius: f32,
+    /// Fraction of the bullet damage taken by the boss.
+    pub(crate) boss_damage_taken: f32,
+    /// Score for destroying the boss, instead of the score of a germ.
+    pub(crate) boss_kill_score: usize,
+    /// Title of the window. `{version}` is replaced with the game version.
+    pub(crate) window_title: String,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            player_wall_elasticity: 0.5,
+            hazard_spawn_rate: 0.2,
+            hazard_lifetime: 4.0,
+            hazard_radius: 12.0,
+            hazard_cell_damage: 4.0,
+            hazard_player_risk: 5,
+            armored_germ_chance: 0.15,
+            armored_germ_hits: 2,
+            splitter_germ_chance: 0.1,
+            splitter_speed: 150.0,
+            shooter_germ_chance: 0.08,
+            shooter_germ_interval: 2.5,
+            enemy_bullet_speed: 250.0,
+            enemy_bullet_radius: 5.0,
+            enemy_bullet_damage: 5,
+            cure_chance: 0.08,
+            cure_radius: 14.0,
+            cure_risk_reduction: 50,
+            health_pickup_chance: 0.05,
+            health_pickup_radius: 14.0,
+            health_pickup_amount: 10,
+            left_zone_width: 220.0 / 320.0,
+            right_zone_width: 220.0 / 320.0,
+            missed_shot_risk: MissedShotRisk::default(),
+            missed_shot_effect_risk: 10,
+            side_effect_duration: 16.0,
+            max_effect_risk: 150,
+            shot_energy_cost: 0.0,
+            energy_regen_rate: 25.0,
+            max_energy: 100.0,
+            shot_heat: 0.0,
+            heat_cooling_rate: 0.5,
+            overheat_recovery: 0.4,
+            max_patient_hp: 100,
+            time_attack_duration: 90.0,
+            time_attack_warning: 10.0,
+            bullet_damage: 12.0,
+            cell_intercollision_damage: 8.0,
+            player_collision_damage: 12.0,
+            background_scroll_speed: 200.0,
+            background_parallax: 0.6,
+            // Knocks a cell drawn at the sprite's original size back at 200 units per second.
+            knockback_impulse: 9000.0 * CELL_TEXTURE_RADIUS,
+            bullet_pool_size: 64,
+            recoil_speed: 120.0,
+            zone_exit_margin: 6.0,
+            spawn_gap_min: 0.0,
+            spawn_gap_max: 60.0,
+            formation_order: FormationOrder::Random,
+            cell_gravity_min: 15.0,
+            cell_gravity_max: 35.0,
+            germ_kill_heal: 0,
+            germ_kill_score: 1,
+            combo_window: 1.5,
+            body_kill_damage: 10,
+            body_exit_heal: 1,
+            germ_exit_damage: 10,
+            boss_exit_damage: 30,
+            bloodstream_speed_base: 1.0,
+            bloodstream_speed_per_point: 0.001,
+            bloodstream_speed_max: 1.5,
+            player_top_margin: 0.0,
+            last_breath_hp: 15,
+            last_breath_speed: 0.8,
+            bullet_speed: 600.0,
+            charge_min_time: 0.2,
+            charge_full_time: 1.0,
+            charge_max_power: 2.5,
+            pierce_score_interval: 500,
+            spread_angle: 8.0,
+            intercept_indicator_min_speed: 200.0,
+            top_camping_time: 0.0,
+            top_camping_fire_slowdown: 3.0,
+            zone_movement_affects_cells: true,
+            cells_stop_at_walls: false,
+            collision_push_factor: 0.6,
+            collision_iterations: 1,
+            spawn_telegraph: 0.5,
+            spawn_protection: 0.0,
+            cell_merge_max_speed: 40.0,
+            cell_merge_chance: 0.3,
+            cell_merge_max_radius: 70.0,
+            mode_music: HashMap::new(),
+            restart_cooldown: 1.0,
+            boss_score_interval: 300,
+            boss_warning_time: 2.0,
+            boss_intro_protection: 2.5,
+            boss_radius: 90.0,
+            boss_damage_taken: 0.25,
+            boss_kill_score: 25,
+            window_title: "Side Vein Effect {version}".to_owned(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub(crate) const FILE_NAME: &str = "config.ron";
+
+    pub(crate) fn load() -> Self {
+        load_ron(Self::FILE_NAME)
+    }
+
+    /// Multiplier for the scrolling and the falling speed of the cells at the given score.
+    pub(crate) fn bloodstream_speed(&self, score: usize) -> f32 {
+        (self.bloodstream_speed_base + self.bloodstream_speed_per_point * score as f32)
+            .min(self.bloodstream_speed_max)
+    }
+
+    /// Random downward acceleration for a spawned cell.
+    pub(crate) fn cell_gravity(&self, rng: &mut impl Rng) -> f32 {
+        let min = self.cell_gravity_min.clamp(0.0, MAX_CELL_GRAVITY);
+        let max = self.cell_gravity_max.clamp(min, MAX_CELL_GRAVITY);
+        rng.gen_range(min..=max)
+    }
+
+    pub(crate) fn window_title(&self) -> String {
+        self.window_title
+            .replace("{version}", concat!("v", env!("CARGO_PKG_VERSION")))
+    }
+}
+
+/// Location of a save file, if the platform has a data directory.
+fn save_path(file_name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("side-vein-effect").join(file_name))
+}
+
+/// Load a save file, falling back to the default when it's missing or corrupt.
+pub(crate) fn load_ron<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    let Some(path) = save_path(file_name) else {
+        return T::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return T::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!("Ignoring corrupt save file {}: {}", path.display(), err);
+        T::default()
+    })
+}
+
+pub(crate) fn save_ron<T: Serialize>(file_name: &str, value: &T) {
+    let Some(path) = save_path(file_name) else {
+        return;
+    };
+    let contents = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!("Failed to serialize {}: {}", path.display(), err);
+            return;
+        }
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, contents));
+    if let Err(err) = result {
+        warn!("Failed to save {}: {}", path.display(), err);
+    }
+}
@@ -0,0 +1,1675 @@
+//! Run lifecycle: setup, game modes, the start and end of runs, and high scores.
+
+use std::time::Duration;
+
+use bevy::{
+    math::vec3, prelude::*, sprite::Mesh2dHandle, text::Text2dBounds, window::PrimaryWindow,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{change_music, MusicResource, SoundEffects, VolumeMeterText},
+    components::{Cell, Physics},
+    config::{load_ron, save_ron, GameConfig},
+    physics::{StepDebug, TIME_STEP},
+    player::{
+        BulletPool, Ghost, GhostKey, Ghosts, Player, PlayerBullet, ReplayRecorder, WeaponState,
+        NANO_COLOR,
+    },
+    settings::{percent_string, KeyBindings, PlayerSkin, Settings},
+    side_effects::{SideEffects, SideFx},
+    spawner::{
+        enemy_count_range, velocity_params, BossFight, Combo, DyingCell, Hazard, Scroller,
+        SpawnWarning, Spawner, VeinSide, Waves, CURE_COLOR, ENEMY_BULLET_COLOR,
+        HEALTH_PICKUP_COLOR, VEIN_TILE_HEIGHT,
+    },
+    ui::{
+        legend_text, spawn_difficulty_chart, BossBanner, CellTooltip, ChainLine, DebugText,
+        FloatingText, InterceptMarker, LastBreathOverlay, LegendText, ModeText, ModifiersText,
+        PauseMenu, ScoreboardText, SettingsText, TextStyles, TipText, TopText, WelcomeText,
+        DIFFICULTY_PREVIEW_MAX_SCORE, TEXT_COLOR,
+    },
+};
+
+const BACKGROUND_COLOR: Color = Color::rgb(0.3, 0.05, 0.025);
+
+/// Image of the window icon, relative to the working directory.
+const WINDOW_ICON_PATH: &str = "assets/graphics/player.png";
+
+/// Game states, run setup and the end of a run.
+pub(crate) struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .insert_resource(Boundaries::default())
+            .insert_resource(GameMode::default())
+            .insert_resource(RunOptions::from_env())
+            .insert_resource(RunModifiers::default())
+            .insert_resource(HighScores::load())
+            .insert_resource(Settings::load())
+            .insert_resource(ClearColor(BACKGROUND_COLOR))
+            .add_startup_system(setup)
+            .add_startup_system(set_window_icon)
+            .add_system(setup_game.in_schedule(OnEnter(GameState::Init)))
+            .add_systems(
+                (start_game, apply_run_modifiers)
+                    .distributive_run_if(starting_run)
+                    .in_schedule(OnEnter(GameState::Running)),
+            )
+            .add_system(
+                finish_resume
+                    .after(start_game)
+                    .after(apply_run_modifiers)
+                    .after(change_music)
+                    .in_schedule(OnEnter(GameState::Running)),
+            )
+            .init_resource::<TriggerKeys>()
+            .add_system(remember_trigger_keys.in_base_set(CoreSet::Last))
+            .add_system(consume_input.in_schedule(OnEnter(GameState::Running)))
+            .add_system(consume_input.in_schedule(OnEnter(GameState::Paused)))
+            .add_system(consume_input.in_schedule(OnEnter(GameState::Init)))
+            .add_system(start_restart_cooldown.in_schedule(OnEnter(GameState::Ended)))
+            .add_systems(
+                (
+                    game_over_check.run_if(in_state(GameState::Running)),
+                    countdown_system.run_if(in_state(GameState::Running)),
+                    fade_system.run_if(in_state(GameState::Running)),
+                )
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(game_over_system.run_if(in_state(GameState::Ended)))
+            .add_system(resize_playfield);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub(crate) enum GameState {
+    #[default]
+    Init,
+    Running,
+    /// The run is frozen while the pause menu is shown.
+    Paused,
+    Ended,
+    /// Developer scene for observing the collision response. Only reachable in debug builds.
+    CollisionLab,
+}
+
+/// Rule set of a run, chosen on the welcome screen.
+#[derive(Resource, Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub(crate) enum GameMode {
+    /// The run ends when the patient's health drops to zero.
+    #[default]
+    Standard,
+    /// Additionally, any germ reaching the bottom infects the patient and ends the run.
+    Sterile,
+    /// Score as much as possible before the countdown ends.
+    TimeAttack,
+}
+
+impl GameMode {
+    pub(crate) fn next(&self) -> GameMode {
+        match self {
+            GameMode::Standard => GameMode::Sterile,
+            GameMode::Sterile => GameMode::TimeAttack,
+            GameMode::TimeAttack => GameMode::Standard,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            GameMode::Standard => "Standard",
+            GameMode::Sterile => "Sterile",
+            GameMode::TimeAttack => "Time attack",
+        }
+    }
+
+    pub(crate) fn description(&self) -> &str {
+        match self {
+            GameMode::Standard => "Keep the patient healthy.",
+            GameMode::Sterile => {
+                "Don't let anything through: a single germ reaching the bottom is game over."
+            }
+            GameMode::TimeAttack => "Score as much as you can before the countdown runs out.",
+        }
+    }
+}
+
+/// Options for the next run, chosen on the welcome screen.
+#[derive(Resource, Default)]
+pub(crate) struct RunOptions {
+    /// Seed the run with the current UTC date so that everyone plays the same run that day.
+    pub(crate) daily: bool,
+    /// Start the run at this score to practice the harder waves. Such runs aren't recorded.
+    pub(crate) practice_score: usize,
+    /// Seed of the last started run.
+    pub(crate) last_seed: Option<u64>,
+    /// Replay the last run's seed and modifiers, skipping the welcome screen.
+    pub(crate) retry: bool,
+    /// Seed of every normal run, given with `--seed` or the `SIDE_VEIN_SEED` environment
+    /// variable to reproduce a run.
+    pub(crate) fixed_seed: Option<u64>,
+}
+
+impl RunOptions {
+    /// Step between the selectable practice scores. Spawn difficulty stops growing at 500.
+    pub(crate) const PRACTICE_SCORE_STEP: usize = 100;
+    pub(crate) const MAX_PRACTICE_SCORE: usize = 500;
+
+    pub(crate) fn next_practice_score(&mut self) {
+        self.practice_score = (self.practice_score + Self::PRACTICE_SCORE_STEP)
+            % (Self::MAX_PRACTICE_SCORE + Self::PRACTICE_SCORE_STEP);
+    }
+
+    pub(crate) fn is_practice(&self) -> bool {
+        self.practice_score > 0
+    }
+
+    pub(crate) fn banner(&self, game_mode: GameMode) -> String {
+        let daily = if self.daily {
+            format!("DAILY CHALLENGE {} (D to toggle)", date_string(utc_day()))
+        } else {
+            "D: Daily challenge".to_owned()
+        };
+        let practice = if self.is_practice() {
+            format!(
+                "PRACTICE FROM SCORE {} (P to change, not recorded)",
+                self.practice_score
+            )
+        } else {
+            "P: Practice from a higher score".to_owned()
+        };
+        format!(
+            "MODE: {} (M to change)\n{}\n{}\n{}",
+            game_mode.name(),
+            game_mode.description(),
+            daily,
+            practice
+        )
+    }
+
+    pub(crate) fn from_env() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--seed").skip(1);
+        let fixed_seed = args
+            .next()
+            .or_else(|| std::env::var("SIDE_VEIN_SEED").ok())
+            .and_then(|seed| match seed.parse() {
+                Ok(seed) => Some(seed),
+                Err(err) => {
+                    warn!("Ignoring invalid seed {}: {}", seed, err);
+                    None
+                }
+            });
+        Self {
+            fixed_seed,
+            ..default()
+        }
+    }
+
+    /// Seed for a run with these options.
+    pub(crate) fn seed(&self) -> u64 {
+        if let Some(seed) = self.last_seed.filter(|_| self.retry) {
+            seed
+        } else if self.daily {
+            utc_day()
+        } else if let Some(seed) = self.fixed_seed {
+            seed
+        } else {
+            rand::random::<u32>() as u64
+        }
+    }
+}
+
+/// Optional rule changes picked before a run, each with an upside and a downside.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum RunModifier {
+    Adrenaline,
+    HeavyRounds,
+    Transfusion,
+    Crowded,
+}
+
+impl RunModifier {
+    pub(crate) const ALL: &[RunModifier] = &[
+        RunModifier::Adrenaline,
+        RunModifier::HeavyRounds,
+        RunModifier::Transfusion,
+        RunModifier::Crowded,
+    ];
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            RunModifier::Adrenaline => "Adrenaline",
+            RunModifier::HeavyRounds => "Heavy rounds",
+            RunModifier::Transfusion => "Transfusion",
+            RunModifier::Crowded => "Crowded",
+        }
+    }
+
+    pub(crate) fn description(&self) -> &str {
+        match self {
+            RunModifier::Adrenaline => "Double score, but germs fall faster.",
+            RunModifier::HeavyRounds => "Double bullet damage, but half the fire rate.",
+            RunModifier::Transfusion => "Start with 50% more health, but blood cells don't heal.",
+            RunModifier::Crowded => "Smaller cells, but more of them.",
+        }
+    }
+}
+
+/// Modifiers offered on the welcome screen and the ones picked for the run.
+#[derive(Resource, Default)]
+pub(crate) struct RunModifiers {
+    pub(crate) offered: Vec<RunModifier>,
+    /// Picked modifiers, kept sorted.
+    pub(crate) active: Vec<RunModifier>,
+}
+
+impl RunModifiers {
+    pub(crate) const OFFERED: usize = 3;
+    pub(crate) const MAX_ACTIVE: usize = 2;
+
+    /// Offer a new random set of modifiers and clear the picked ones.
+    pub(crate) fn reroll(&mut self) {
+        self.offered = rand::seq::index::sample(
+            &mut rand::thread_rng(),
+            RunModifier::ALL.len(),
+            Self::OFFERED,
+        )
+        .into_iter()
+        .map(|i| RunModifier::ALL[i])
+        .collect();
+        self.active.clear();
+    }
+
+    /// Pick or unpick the offered modifier with the given index.
+    pub(crate) fn toggle(&mut self, index: usize) {
+        let Some(&modifier) = self.offered.get(index) else {
+            return;
+        };
+        if let Some(i) = self.active.iter().position(|m| *m == modifier) {
+            self.active.remove(i);
+        } else if self.active.len() < Self::MAX_ACTIVE {
+            self.active.push(modifier);
+            self.active.sort();
+        }
+    }
+
+    pub(crate) fn has(&self, modifier: RunModifier) -> bool {
+        self.active.contains(&modifier)
+    }
+
+    /// Points awarded for each germ hit.
+    pub(crate) fn score_multiplier(&self) -> usize {
+        if self.has(RunModifier::Adrenaline) {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub(crate) fn banner(&self) -> String {
+        let mut text = format!(
+            "MODIFIERS (1-{} to pick up to {})",
+            Self::OFFERED,
+            Self::MAX_ACTIVE
+        );
+        for (i, modifier) in self.offered.iter().enumerate() {
+            let check = if self.has(*modifier) { "x" } else { " " };
+            text += &format!(
+                "\n[{}] {}. {}: {}",
+                check,
+                i + 1,
+                modifier.name(),
+                modifier.description()
+            );
+        }
+        text
+    }
+}
+
+/// All the randomness of a run is drawn from here, so that a seed reproduces the run.
+#[derive(Resource)]
+pub(crate) struct GameRng {
+    pub(crate) seed: u64,
+    pub(crate) rng: StdRng,
+}
+
+impl GameRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// Number of days since the Unix epoch in UTC. This is the seed of the daily challenge.
+fn utc_day() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_millis() as f64);
+    #[cfg(target_arch = "wasm32")]
+    let millis = js_sys::Date::now();
+    (millis / 86_400_000.0) as u64
+}
+
+/// Format a day number returned by [`utc_day`] as `YYYY-MM-DD`.
+pub(crate) fn date_string(days: u64) -> String {
+    // Civil date from days algorithm by Howard Hinnant
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Result of a finished run.
+#[derive(Serialize, Deserialize)]
+struct HighScoreEntry {
+    score: usize,
+    mode: GameMode,
+    /// Date of the daily challenge, if this was a daily run.
+    daily: Option<String>,
+    /// Sorted modifiers of the run.
+    modifiers: Vec<RunModifier>,
+}
+
+impl HighScoreEntry {
+    /// Whether the two runs are comparable, i.e. belong to the same leaderboard.
+    fn same_board(&self, other: &HighScoreEntry) -> bool {
+        self.mode == other.mode && self.daily == other.daily && self.modifiers == other.modifiers
+    }
+}
+
+/// Remaining time of a time attack run.
+#[derive(Resource)]
+pub(crate) struct CountdownTimer {
+    pub(crate) timer: Timer,
+}
+
+/// Best scores, persisted between sessions. Each game mode and daily challenge has its own
+/// leaderboard.
+#[derive(Resource, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct HighScores {
+    entries: Vec<HighScoreEntry>,
+}
+
+impl HighScores {
+    const FILE_NAME: &str = "highscores.ron";
+    /// Number of runs kept on each leaderboard.
+    const BOARD_SIZE: usize = 10;
+
+    fn load() -> Self {
+        load_ron(Self::FILE_NAME)
+    }
+
+    fn save(&self) {
+        save_ron(Self::FILE_NAME, self);
+    }
+
+    /// Record a finished run and return the best score on its leaderboard.
+    fn record(&mut self, entry: HighScoreEntry) -> usize {
+        let best = self
+            .entries
+            .iter()
+            .filter(|other| other.same_board(&entry))
+            .map(|other| other.score)
+            .fold(entry.score, usize::max);
+        self.entries.push(entry);
+        self.entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        let mut kept: Vec<HighScoreEntry> = Vec::new();
+        for entry in std::mem::take(&mut self.entries) {
+            if kept.iter().filter(|other| other.same_board(&entry)).count() < Self::BOARD_SIZE {
+                kept.push(entry);
+            }
+        }
+        self.entries = kept;
+        best
+    }
+}
+
+/// Delay before the game over screen accepts the restart key, so that the results can be read.
+#[derive(Resource)]
+struct RestartCooldown {
+    timer: Timer,
+}
+
+/// Fades a sprite or text in after spawning, and out before despawning it.
+#[derive(Component)]
+pub(crate) struct Fade {
+    pub(crate) timer: Timer,
+    pub(crate) fading_out: bool,
+    /// Alpha when fully faded in.
+    pub(crate) alpha: f32,
+}
+
+impl Fade {
+    /// Duration of a fade in seconds.
+    pub(crate) const DURATION: f32 = 0.3;
+    pub(crate) const REDUCED_MOTION_DURATION: f32 = 0.05;
+
+    pub(crate) fn fade_in(alpha: f32, settings: &Settings) -> Self {
+        let duration = if settings.reduce_motion {
+            Self::REDUCED_MOTION_DURATION
+        } else {
+            Self::DURATION
+        };
+        Self {
+            timer: Timer::from_seconds(duration, TimerMode::Once),
+            fading_out: false,
+            alpha,
+        }
+    }
+
+    /// Start fading out, after which the entity is despawned.
+    pub(crate) fn fade_out(&mut self) {
+        if !self.fading_out {
+            self.fading_out = true;
+            // Fade out from the current alpha if the fade in is not finished yet.
+            let elapsed = self.timer.duration().mul_f32(1.0 - self.timer.percent());
+            self.timer.reset();
+            self.timer.set_elapsed(elapsed);
+        }
+    }
+
+    pub(crate) fn current_alpha(&self) -> f32 {
+        if self.fading_out {
+            self.alpha * self.timer.percent_left()
+        } else {
+            self.alpha * self.timer.percent()
+        }
+    }
+}
+
+/// Tracks score, player health, etc.
+#[derive(Resource)]
+pub(crate) struct Scoreboard {
+    pub(crate) score: usize,
+    pub(crate) patient_hp: i32,
+    /// Set when a germ reaches the bottom in sterile mode.
+    pub(crate) infected: bool,
+    pub(crate) shots_fired: usize,
+    /// Shots that struck at least one germ.
+    pub(crate) shots_hit: usize,
+    /// Shots that left the playfield or ran out of range without hitting anything.
+    pub(crate) shots_wasted: usize,
+}
+
+impl Scoreboard {
+    pub(crate) fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.shots_hit as f32 / self.shots_fired as f32
+        }
+    }
+}
+
+impl Default for Scoreboard {
+    fn default() -> Self {
+        Self {
+            score: 0,
+            patient_hp: 100,
+            infected: false,
+            shots_fired: 0,
+            shots_hit: 0,
+            shots_wasted: 0,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct Boundaries {
+    pub(crate) left_wall: f32,
+    pub(crate) right_wall: f32,
+    pub(crate) top: f32,
+    pub(crate) bottom: f32,
+}
+
+/// Width of the side areas next to the playfield, which are used for the HUD.
+const SIDE_AREA_WIDTH: f32 = 320.0;
+
+/// Distance from the center to the walls for the given window width.
+fn playfield_half_width(window_width: f32, wide: bool) -> f32 {
+    let default_half_width = Boundaries::default().right_wall;
+    if wide {
+        (window_width / 2.0 - SIDE_AREA_WIDTH).max(default_half_width)
+    } else {
+        default_half_width
+    }
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Boundaries {
+            left_wall: -320.0,
+            right_wall: 320.0,
+            top: 360.0,
+            bottom: -360.0,
+        }
+    }
+}
+
+/// Bevy doesn't support window icons yet, so set it on the winit window directly.
+#[cfg(not(target_arch = "wasm32"))]
+fn set_window_icon(
+    winit_windows: NonSend<bevy::winit::WinitWindows>,
+    query: Query<Entity, With<PrimaryWindow>>,
+) {
+    let Some(window) = query
+        .get_single()
+        .ok()
+        .and_then(|entity| winit_windows.get_window(entity))
+    else {
+        return;
+    };
+    let image = match image::open(WINDOW_ICON_PATH) {
+        Ok(image) => image.into_rgba8(),
+        Err(err) => {
+            warn!(
+                "Failed to load the window icon {}: {}",
+                WINDOW_ICON_PATH, err
+            );
+            return;
+        }
+    };
+    let (width, height) = image.dimensions();
+    match winit::window::Icon::from_rgba(image.into_raw(), width, height) {
+        Ok(icon) => window.set_window_icon(Some(icon)),
+        Err(err) => warn!("Invalid window icon: {}", err),
+    }
+}
+
+/// The browser tab uses the page's favicon instead.
+#[cfg(target_arch = "wasm32")]
+fn set_window_icon() {}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+) {
+    // Camera
+    commands.spawn(Camera2dBundle::default());
+
+    commands.insert_resource(KeyBindings::load());
+    commands.insert_resource(Waves {
+        script: asset_server.load("waves.ron"),
+    });
+
+    let circle_mesh: Mesh2dHandle = meshes.add(shape::Circle::default().into()).into();
+    let nano_color = materials.add(ColorMaterial::from(NANO_COLOR));
+    let cure_color = materials.add(ColorMaterial::from(CURE_COLOR));
+    let health_pickup_color = materials.add(ColorMaterial::from(HEALTH_PICKUP_COLOR));
+    let enemy_bullet_color = materials.add(ColorMaterial::from(ENEMY_BULLET_COLOR));
+
+    let spawner = Spawner {
+        timer: Timer::from_seconds(10.0, TimerMode::Repeating),
+        waves: 0,
+        circle_mesh,
+        nano_color,
+        cure_color,
+        health_pickup_color,
+        enemy_bullet_color,
+        player_textures: PlayerSkin::ALL
+            .iter()
+            .map(|skin| (*skin, asset_server.load(skin.texture_path())))
+            .collect(),
+        blood_texture: asset_server.load("graphics/bloodcell.png"),
+        germ_texture: asset_server.load("graphics/germ.png"),
+        armored_germ_texture: asset_server.load("graphics/armored_germ.png"),
+        shooter_germ_texture: asset_server.load("graphics/shooter_germ.png"),
+        vein_side_texture: asset_server.load("graphics/veinside.png"),
+        vein_bg_texture: asset_server.load("graphics/veinbg.png"),
+    };
+    let free = (0..config.bullet_pool_size)
+        .map(|_| commands.spawn(BulletPool::bundle(&spawner)).id())
+        .collect();
+    commands.insert_resource(BulletPool { free, spawned: 0 });
+    commands.insert_resource(spawner);
+
+    commands.insert_resource(MusicResource {
+        game_source: asset_server.load("music/game.ogg"),
+        mode_sources: config
+            .mode_music
+            .iter()
+            .map(|(mode, path)| (*mode, asset_server.load(path.as_str())))
+            .collect(),
+        over_source: asset_server.load("music/over.ogg"),
+        current: None,
+    });
+    commands.insert_resource(SoundEffects {
+        shoot: asset_server.load("sounds/shoot.ogg"),
+        germ_killed: asset_server.load("sounds/germ_killed.ogg"),
+        cell_lost: asset_server.load("sounds/cell_lost.ogg"),
+        heartbeat: asset_server.load("sounds/heartbeat.ogg"),
+    });
+
+    let label_style = TextStyle {
+        font: asset_server.load("fonts/Kanit-Regular.ttf"),
+        font_size: 32.0,
+        color: TEXT_COLOR,
+    };
+    let number_style = TextStyle {
+        font: asset_server.load("fonts/Kanit-Regular.ttf"),
+        font_size: 64.0,
+        color: TEXT_COLOR,
+    };
+
+    commands.insert_resource(TextStyles {
+        in_game: TextStyle {
+            font: asset_server.load("fonts/Kanit-Regular.ttf"),
+            font_size: 42.0,
+            color: TEXT_COLOR,
+        },
+        label_style: label_style.clone(),
+    });
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::all(Val::Percent(100.)),
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.8, 0.0, 0.0, 0.0).into(),
+            ..Default::default()
+        },
+        LastBreathOverlay,
+    ));
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                // fill the entire window
+                size: Size::all(Val::Percent(100.)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Start,
+                padding: UiRect {
+                    left: Val::Px(8.0),
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "Left Side-Effect Risk",
+                label_style.clone(),
+            ));
+            builder.spawn((
+                TextBundle::from_section("0", number_style.clone()),
+                ScoreboardText::LeftEffectRisk,
+            ));
+            builder.spawn(TextBundle::from_section("Score", label_style.clone()));
+            builder.spawn((
+                TextBundle::from_section("0", number_style.clone()),
+                ScoreboardText::Score,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                ScoreboardText::TimeLeft,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                ScoreboardText::Seed,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                ScoreboardText::Energy,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                ScoreboardText::Heat,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                ScoreboardText::GermCount,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                // fill the entire window
+                size: Size::all(Val::Percent(100.)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::End,
+                padding: UiRect {
+                    left: Val::Px(8.0),
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "Right Side-Effect Risk",
+                label_style.clone(),
+            ));
+            builder.spawn((
+                TextBundle::from_section("0", number_style.clone()),
+                ScoreboardText::RightEffectRisk,
+            ));
+            builder.spawn((
+                TextBundle::from_section("Patient Health", label_style.clone()),
+                ScoreboardText::PatientHpLabel,
+            ));
+            builder.spawn((
+                TextBundle::from_section("100", number_style.clone()),
+                ScoreboardText::PatientHp,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // fill the entire window
+                size: Size::all(Val::Percent(100.)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                // align_self: AlignSelf::Center,
+                padding: UiRect {
+                    left: Val::Px(8.0),
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section("", number_style.clone()),
+                TopText::Header,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                TopText::Sub,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", label_style.clone()),
+                TopText::Prompt,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::all(Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section("", label_style.clone())
+                        .with_text_alignment(TextAlignment::Center)
+                        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.85))
+                        .with_style(Style {
+                            padding: UiRect::all(Val::Px(16.0)),
+                            ..Default::default()
+                        })
+                },
+                SettingsText,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(20.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section("", label_style.clone())
+                        .with_text_alignment(TextAlignment::Center)
+                        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.6))
+                        .with_style(Style {
+                            padding: UiRect::all(Val::Px(8.0)),
+                            ..Default::default()
+                        })
+                },
+                TipText,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(35.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section("WARNING: BOSS APPROACHING", number_style.clone())
+                        .with_background_color(Color::rgba(0.6, 0.0, 0.0, 0.7))
+                        .with_style(Style {
+                            padding: UiRect::all(Val::Px(8.0)),
+                            ..Default::default()
+                        })
+                },
+                BossBanner,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                    ..default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        ..label_style.clone()
+                    },
+                ),
+                VolumeMeterText,
+            ));
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(8.0),
+                    bottom: Val::Px(8.0),
+                    ..default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 20.0,
+                            ..label_style.clone()
+                        },
+                    )
+                },
+                DebugText,
+            ));
+        });
+
+    commands.spawn((
+        TextBundle {
+            visibility: Visibility::Hidden,
+            ..TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 18.0,
+                    ..label_style.clone()
+                },
+            )
+            .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.7))
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            })
+        },
+        CellTooltip,
+    ));
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(8.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle {
+                    visibility: Visibility::Hidden,
+                    ..TextBundle::from_section(
+                        legend_text(),
+                        TextStyle {
+                            font_size: 24.0,
+                            ..label_style.clone()
+                        },
+                    )
+                    .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.75))
+                    .with_style(Style {
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    })
+                },
+                LegendText,
+            ));
+        });
+}
+
+/// Add the game's entities
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn setup_game(
+    mut commands: Commands,
+    mut spawner: ResMut<Spawner>,
+    boundaries: Res<Boundaries>,
+    mut pool: ResMut<BulletPool>,
+    query: Query<
+        (Entity, Option<&PlayerBullet>),
+        Or<(
+            With<Physics>,
+            With<SideFx>,
+            With<Scroller>,
+            With<Hazard>,
+            With<DyingCell>,
+            With<Ghost>,
+            With<Cell>,
+            With<SpawnWarning>,
+            With<FloatingText>,
+            With<ChainLine>,
+            With<InterceptMarker>,
+        )>,
+    >,
+    mut top_text_query: Query<(&mut Text, &TopText)>,
+    text_styles: Res<TextStyles>,
+    game_mode: Res<GameMode>,
+    run_options: Res<RunOptions>,
+    mut run_modifiers: ResMut<RunModifiers>,
+    config: Res<GameConfig>,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if run_options.retry {
+        next_state.set(GameState::Running);
+    } else {
+        run_modifiers.reroll();
+    }
+    for (entity, bullet) in &query {
+        // Bullets still in flight go back to the pool, which keeps their entities.
+        if bullet.is_some() {
+            pool.release(&mut commands, entity);
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (mut text, top_text) in &mut top_text_query {
+        text.sections[0].value = match top_text {
+            TopText::Header => "Side Vein Effect".to_owned(),
+            TopText::Sub => "Press ENTER to start, TAB for settings.".to_owned(),
+            TopText::Prompt => String::new(),
+        };
+    }
+
+    for i in 0..4 {
+        let x_mul: f32 = if i % 2 == 0 { 1.0 } else { -1.0 };
+        let y: f32 = if i >= 2 { 1080.0 } else { -360.0 };
+        let transform = Transform {
+            translation: vec3(x_mul * (boundaries.right_wall + SIDE_AREA_WIDTH), y, 0.0),
+            ..default()
+        };
+        commands.spawn((
+            SpriteBundle {
+                transform,
+                texture: spawner.vein_side_texture.clone(),
+                ..default()
+            },
+            Scroller {
+                parallax_factor: 1.0,
+                height: VEIN_TILE_HEIGHT,
+            },
+            VeinSide(x_mul),
+        ));
+    }
+
+    for i in 0..2 {
+        let y: f32 = if i > 0 { 1080.0 } else { -360.0 };
+        let transform = Transform {
+            translation: vec3(0.0, y, 0.0),
+            scale: vec3(
+                boundaries.right_wall / Boundaries::default().right_wall,
+                1.0,
+                1.0,
+            ),
+            ..default()
+        };
+        commands.spawn((
+            SpriteBundle {
+                transform,
+                texture: spawner.vein_bg_texture.clone(),
+                ..default()
+            },
+            Scroller {
+                parallax_factor: config.background_parallax,
+                height: VEIN_TILE_HEIGHT,
+            },
+        ));
+    }
+
+    // PLAYER
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 10.0))
+                .with_scale(settings.player_skin.scale()),
+            texture: spawner.player_textures[&settings.player_skin].clone(),
+            ..default()
+        },
+        Player::physics(&config),
+        Player::new(&settings, &config),
+    ));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Percent(16.0),
+                        left: Val::Px(0.0),
+                        right: Val::Px(0.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            WelcomeText,
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    run_options.banner(*game_mode),
+                    text_styles.label_style.clone(),
+                )
+                .with_text_alignment(TextAlignment::Center),
+                WelcomeText,
+                ModeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    run_modifiers.banner(),
+                    TextStyle {
+                        font_size: 24.0,
+                        ..text_styles.label_style.clone()
+                    },
+                ),
+                WelcomeText,
+                ModifiersText,
+            ));
+        });
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Percent(60.0),
+                        bottom: Val::Percent(100.0),
+                        left: Val::Px(0.0),
+                        right: Val::Px(0.0),
+                    },
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    padding: UiRect {
+                        left: Val::Px(8.0),
+                        top: Val::Px(8.0),
+                        right: Val::Px(8.0),
+                        bottom: Val::Px(8.0),
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            WelcomeText,
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section("CONTROLS", text_styles.label_style.clone()),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section("Arrow keys: Move", text_styles.label_style.clone()),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "Space or A: Shoot, S: Secondary weapon",
+                    text_styles.label_style.clone(),
+                ),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "L: Show side-effect legend",
+                    text_styles.label_style.clone(),
+                ),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section("GAMEPLAY", text_styles.label_style.clone()),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "Let the blood cells (red cells) pass. Shoot germs (green cells).",
+                    text_styles.label_style.clone(),
+                ),
+                WelcomeText,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "If you miss shots, side-effect risk will increase.",
+                    text_styles.label_style.clone(),
+                ),
+                WelcomeText,
+            ));
+        });
+
+    let small_style = TextStyle {
+        font_size: 20.0,
+        ..text_styles.label_style.clone()
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Percent(45.0),
+                        right: Val::Px(8.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            WelcomeText,
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                format!("DIFFICULTY, SCORE 0-{}", DIFFICULTY_PREVIEW_MAX_SCORE),
+                small_style.clone(),
+            ));
+            spawn_difficulty_chart(builder, "Cells per wave", &small_style, |score| {
+                let range = enemy_count_range(score);
+                (range.start + range.end - 1) as f32 / 2.0
+            });
+            spawn_difficulty_chart(builder, "Fall speed", &small_style, |score| {
+                (-velocity_params(score).1 + 50.0) * config.bloodstream_speed(score)
+            });
+            spawn_difficulty_chart(builder, "Sideways speed", &small_style, |score| {
+                velocity_params(score).0
+            });
+        });
+
+    commands.insert_resource(Scoreboard {
+        patient_hp: config.max_patient_hp,
+        ..default()
+    });
+    commands.insert_resource(CountdownTimer {
+        timer: Timer::from_seconds(config.time_attack_duration, TimerMode::Once),
+    });
+    commands.insert_resource(BossFight::new(&config));
+    spawner.waves = 0;
+    commands.insert_resource(Combo::default());
+    commands.insert_resource(WeaponState::new(&settings));
+    let mut side_effects = SideEffects::new(&config);
+    side_effects.fit_zones(&boundaries, &config);
+    commands.insert_resource(side_effects);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_game(
+    mut commands: Commands,
+    query: Query<Entity, With<WelcomeText>>,
+    mut top_text_query: Query<&mut Text, With<TopText>>,
+    mut run_options: ResMut<RunOptions>,
+    mut scoreboard: ResMut<Scoreboard>,
+    game_mode: Res<GameMode>,
+    run_modifiers: Res<RunModifiers>,
+    settings: Res<Settings>,
+    spawner: Res<Spawner>,
+    mut ghosts: ResMut<Ghosts>,
+    mut step_debug: ResMut<StepDebug>,
+) {
+    let seed = run_options.seed();
+    run_options.last_seed = Some(seed);
+    run_options.retry = false;
+    step_debug.frame = 0;
+    commands.insert_resource(GameRng::new(seed));
+    scoreboard.score = run_options.practice_score;
+    ghosts.current.clear();
+    let key = GhostKey::new(*game_mode, seed, &run_modifiers);
+    if settings.show_ghost && ghosts.best.contains_key(&key) {
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 9.0))
+                    .with_scale(settings.player_skin.scale()),
+                texture: spawner.player_textures[&settings.player_skin].clone(),
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.35),
+                    ..default()
+                },
+                ..default()
+            },
+            Ghost,
+        ));
+    }
+    for entity in &query {
+        commands.entity(entity).despawn();
+    }
+    for mut text in &mut top_text_query {
+        text.sections[0].value = "".to_owned();
+    }
+}
+
+/// Apply the modifiers that change the initial state of a run.
+pub(crate) fn apply_run_modifiers(
+    run_modifiers: Res<RunModifiers>,
+    config: Res<GameConfig>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut player_query: Query<&mut Player>,
+) {
+    if run_modifiers.has(RunModifier::Transfusion) {
+        scoreboard.patient_hp = config.max_patient_hp * 3 / 2;
+    }
+    if run_modifiers.has(RunModifier::HeavyRounds) {
+        for mut player in &mut player_query {
+            let duration = player.shoot_timer.duration() * 2;
+            player.shoot_timer.set_duration(duration);
+        }
+    }
+}
+
+/// Move the walls when the window is resized or the wide playfield setting changes.
+fn resize_playfield(
+    settings: Res<Settings>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut boundaries: ResMut<Boundaries>,
+    side_effects: Option<ResMut<SideEffects>>,
+    config: Res<GameConfig>,
+    mut vein_query: Query<(&mut Transform, Option<&VeinSide>), With<Scroller>>,
+    mut fx_query: Query<(&mut Transform, &SideFx, Option<&mut Text2dBounds>), Without<Scroller>>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let half_width = playfield_half_width(window.width(), settings.wide_playfield);
+    if half_width == boundaries.right_wall {
+        return;
+    }
+    boundaries.left_wall = -half_width;
+    boundaries.right_wall = half_width;
+    if let Some(mut side_effects) = side_effects {
+        side_effects.fit_zones(&boundaries, &config);
+        // Keep the drawn zones of the active effects in line with the gameplay zones.
+        for (mut transform, side, bounds) in &mut fx_query {
+            let (center_x, width) = side_effects.zone_span(*side, &boundaries);
+            transform.translation.x = center_x;
+            match bounds {
+                Some(mut bounds) => bounds.size.x = width,
+                None => transform.scale.x = width,
+            }
+        }
+    }
+    for (mut transform, side) in &mut vein_query {
+        match side {
+            Some(VeinSide(x_mul)) => {
+                transform.translation.x = x_mul * (half_width + SIDE_AREA_WIDTH)
+            }
+            None => transform.scale.x = half_width / Boundaries::default().right_wall,
+        }
+    }
+}
+
+fn fade_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Fade, Option<&mut Sprite>, Option<&mut Text>)>,
+) {
+    for (entity, mut fade, sprite, text) in &mut query {
+        fade.timer.tick(Duration::from_secs_f32(TIME_STEP));
+        if fade.fading_out && fade.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let alpha = fade.current_alpha();
+        if let Some(mut sprite) = sprite {
+            sprite.color.set_a(alpha);
+        }
+        if let Some(mut text) = text {
+            for section in &mut text.sections {
+                section.style.color.set_a(alpha);
+            }
+        }
+    }
+}
+
+fn countdown_system(game_mode: Res<GameMode>, mut countdown: ResMut<CountdownTimer>) {
+    if *game_mode == GameMode::TimeAttack {
+        countdown.timer.tick(Duration::from_secs_f32(TIME_STEP));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn game_over_check(
+    scoreboard: Res<Scoreboard>,
+    game_mode: Res<GameMode>,
+    countdown: Res<CountdownTimer>,
+    run_options: Res<RunOptions>,
+    run_modifiers: Res<RunModifiers>,
+    game_rng: Res<GameRng>,
+    mut high_scores: ResMut<HighScores>,
+    mut ghosts: ResMut<Ghosts>,
+    settings: Res<Settings>,
+    recorder: Res<ReplayRecorder>,
+    mut query: Query<(&mut Text, &TopText)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let time_up = *game_mode == GameMode::TimeAttack && countdown.timer.finished();
+    if scoreboard.patient_hp <= 0 || scoreboard.infected || time_up {
+        let result = if run_options.is_practice() {
+            "PRACTICE RUN, NOT RECORDED".to_owned()
+        } else if recorder.playback.is_some() {
+            "REPLAY, NOT RECORDED".to_owned()
+        } else if settings.game_speed != 1.0 {
+            format!(
+                "GAME SPEED {}, NOT RECORDED",
+                percent_string(settings.game_speed)
+            )
+        } else {
+            let daily = run_options.daily.then(|| date_string(game_rng.seed));
+            let best = high_scores.record(HighScoreEntry {
+                score: scoreboard.score,
+                mode: *game_mode,
+                daily,
+                modifiers: run_modifiers.active.clone(),
+            });
+            high_scores.save();
+            ghosts.finish_run(
+                GhostKey::new(*game_mode, game_rng.seed, &run_modifiers),
+                scoreboard.score,
+            );
+            format!(
+                "{} BEST: {}",
+                game_mode.name().to_uppercase(),
+                settings.thousands_separator.format(best as i64)
+            )
+        };
+        let stats = format!(
+            "SHOTS {}  HITS {}  WASTED {}  ACCURACY {}",
+            scoreboard.shots_fired,
+            scoreboard.shots_hit,
+            scoreboard.shots_wasted,
+            percent_string(scoreboard.accuracy())
+        );
+        for (mut text, text_type) in &mut query {
+            text.sections[0].value = match text_type {
+                TopText::Header if scoreboard.infected => "PATIENT INFECTED".to_owned(),
+                TopText::Header if time_up && scoreboard.patient_hp > 0 => "TIME UP".to_owned(),
+                TopText::Header => "GAME OVER".to_owned(),
+                TopText::Sub => format!("{result}\n{stats}"),
+                // Set by `game_over_system` after the restart cooldown.
+                TopText::Prompt => String::new(),
+            };
+        }
+        next_state.set(GameState::Ended);
+    }
+}
+
+fn start_restart_cooldown(mut commands: Commands, config: Res<GameConfig>) {
+    commands.insert_resource(RestartCooldown {
+        timer: Timer::from_seconds(config.restart_cooldown, TimerMode::Once),
+    });
+}
+
+fn game_over_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut cooldown: ResMut<RestartCooldown>,
+    mut query: Query<(&mut Text, &TopText)>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut run_options: ResMut<RunOptions>,
+) {
+    let seed = run_options.last_seed.unwrap_or_default();
+    let ready = cooldown.timer.tick(time.delta()).finished();
+    for (mut text, top_text) in &mut query {
+        if *top_text != TopText::Prompt {
+            continue;
+        }
+        let section = &mut text.sections[0];
+        if ready {
+            section.value = format!("SEED {}\nPRESS R TO RESTART, T TO RETRY THIS SEED", seed);
+            section.style.color = TEXT_COLOR;
+        } else {
+            section.value = format!(
+                "RESTART AVAILABLE IN {:.1}",
+                cooldown.timer.remaining_secs()
+            );
+            section.style.color = *TEXT_COLOR.clone().set_a(0.5);
+        }
+    }
+    if ready && keyboard_input.just_pressed(KeyCode::R) {
+        next_state.set(GameState::Init);
+    }
+    if ready && keyboard_input.just_pressed(KeyCode::T) {
+        run_options.retry = true;
+        next_state.set(GameState::Init);
+    }
+}
+
+/// Whether entering [`GameState::Running`] starts a new run rather than resuming a paused one.
+pub(crate) fn starting_run(pause_menu: Res<PauseMenu>) -> bool {
+    !pause_menu.resuming
+}
+
+fn finish_resume(mut pause_menu: ResMut<PauseMenu>) {
+    pause_menu.resuming = false;
+}
+
+/// Keys pressed on the last frame. State changes are applied on the frame after the key press
+/// requesting them, so these are the keys that may have triggered one.
+#[derive(Resource, Default)]
+struct TriggerKeys(Vec<KeyCode>);
+
+fn remember_trigger_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut trigger_keys: ResMut<TriggerKeys>,
+) {
+    trigger_keys.0 = keyboard_input.get_just_pressed().copied().collect();
+}
+
+/// Forget the key that caused a state change, so that e.g. a key starting the game can't also
+/// shoot. Other held keys, like the movement keys, stay pressed.
+fn consume_input(mut keyboard_input: ResMut<Input<KeyCode>>, trigger_keys: Res<TriggerKeys>) {
+    for key in &trigger_keys.0 {
+        keyboard_input.reset(*key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::SoundEvent,
+        player::{player_shoot, read_player_input, InputLatency, PlayerInput},
+    };
+
+    /// Start the run with a shoot key, as the welcome screen would if it were bound to it.
+    fn start_with_space(
+        keyboard_input: Res<Input<KeyCode>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::Space) {
+            next_state.set(GameState::Running);
+        }
+    }
+
+    #[test]
+    fn key_starting_the_run_doesnt_shoot() {
+        let config = GameConfig::default();
+        let settings = Settings::default();
+        let mut player = Player::new(&settings, &config);
+        player.shoot_timer = Timer::from_seconds(0.0, TimerMode::Once);
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .add_event::<SoundEvent>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<TriggerKeys>()
+            .insert_resource(Time::default())
+            .insert_resource(KeyBindings::default())
+            .insert_resource(ReplayRecorder::default())
+            .insert_resource(PlayerInput::default())
+            .insert_resource(Spawner::default())
+            .insert_resource(SideEffects::new(&config))
+            .insert_resource(WeaponState::new(&settings))
+            .insert_resource(Scoreboard::default())
+            .insert_resource(InputLatency::default())
+            .insert_resource(BulletPool::default())
+            .insert_resource(settings)
+            .add_system(consume_input.in_schedule(OnEnter(GameState::Running)))
+            .add_system(start_with_space)
+            .add_system(remember_trigger_keys.in_base_set(CoreSet::Last))
+            .add_systems(
+                (read_player_input, player_shoot.after(read_player_input))
+                    .distributive_run_if(in_state(GameState::Running)),
+            );
+        app.world
+            .spawn((TransformBundle::default(), Player::physics(&config), player));
+        app.insert_resource(config);
+        // Hold a movement key, then press the shoot key. Both are still held on the frame after,
+        // when the run starts.
+        for key in [KeyCode::Left, KeyCode::Space] {
+            app.world.resource_mut::<Input<KeyCode>>().press(key);
+            app.update();
+            app.world.resource_mut::<Input<KeyCode>>().clear();
+        }
+        app.update();
+        assert_eq!(
+            app.world.resource::<State<GameState>>().0,
+            GameState::Running
+        );
+        let mut bullets = app.world.query_filtered::<(), With<PlayerBullet>>();
+        assert_eq!(bullets.iter(&app.world).count(), 0);
+        assert!(app.world.resource::<PlayerInput>().left);
+    }
+
+    #[test]
+    fn restart_key_is_ignored_during_the_cooldown() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<NextState<GameState>>()
+            .insert_resource(Time::default())
+            .insert_resource(RunOptions::default())
+            .insert_resource(RestartCooldown {
+                timer: Timer::from_seconds(1.0, TimerMode::Once),
+            })
+            .add_system(game_over_system);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::R);
+        app.update();
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, None);
+
+        app.world
+            .resource_mut::<RestartCooldown>()
+            .timer
+            .tick(Duration::from_secs(1));
+        app.update();
+        assert_eq!(
+            app.world.resource::<NextState<GameState>>().0,
+            Some(GameState::Init)
+        );
+    }
+
+    #[test]
+    fn retry_key_restarts_with_the_same_seed() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<NextState<GameState>>()
+            .insert_resource(Time::default())
+            .insert_resource(RunOptions {
+                last_seed: Some(42),
+                ..default()
+            })
+            .insert_resource(RestartCooldown {
+                timer: Timer::from_seconds(0.0, TimerMode::Once),
+            })
+            .add_system(game_over_system);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::T);
+        app.update();
+        assert_eq!(
+            app.world.resource::<NextState<GameState>>().0,
+            Some(GameState::Init)
+        );
+        let run_options = app.world.resource::<RunOptions>();
+        assert!(run_options.retry);
+        assert_eq!(run_options.seed(), 42);
+    }
+}
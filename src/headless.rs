@@ -0,0 +1,157 @@
+//! Windowless run of the simulation with scripted input, for profiling.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    audio::SoundEvent,
+    components::Cell,
+    config::GameConfig,
+    game::{Boundaries, GameMode, GameRng, RunModifiers, RunOptions, Scoreboard},
+    physics::{
+        cell_cell_collisions, enemy_bullet_collisions, physics_objects, player_bullet_collisions,
+        player_collisions, TIME_STEP,
+    },
+    player::{
+        player_bullet_despawner, player_movement, player_shoot, BulletPool, InputLatency, Player,
+        PlayerInput, WeaponState,
+    },
+    settings::Settings,
+    side_effects::{SideEffectUpdateEvent, SideEffects},
+    spawner::{
+        cell_despawner, dying_cell_system, shooter_germ_system, spawn_protection_system,
+        spawner_system, telegraph_system, BossFight, Combo, Spawner, WaveScript, Waves,
+    },
+    ui::{ScreenShake, TextStyles, TipEvent},
+};
+
+/// Fixed update ticks run by `--headless` when no count is given, 100 seconds of gameplay.
+pub(crate) const HEADLESS_DEFAULT_TICKS: u32 = 14_400;
+
+/// Run the spawning, physics and collision systems without a window for the given number of
+/// fixed update ticks, with the player sweeping across the playfield while shooting. Prints the
+/// timing stats before exiting, to profile the simulation.
+pub(crate) fn run_headless(ticks: u32) {
+    let config = GameConfig::load();
+    let settings = Settings::default();
+    let boundaries = Boundaries::default();
+    let mut side_effects = SideEffects::new(&config);
+    side_effects.fit_zones(&boundaries, &config);
+    let spawner = Spawner {
+        timer: Timer::from_seconds(10.0, TimerMode::Repeating),
+        ..default()
+    };
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<WaveScript>()
+        .add_event::<SideEffectUpdateEvent>()
+        .add_event::<TipEvent>()
+        .add_event::<SoundEvent>()
+        .insert_resource(FixedTime::new_from_secs(TIME_STEP))
+        .insert_resource(Scoreboard {
+            patient_hp: config.max_patient_hp,
+            ..default()
+        })
+        .insert_resource(TextStyles {
+            in_game: TextStyle::default(),
+            label_style: TextStyle::default(),
+        })
+        .insert_resource(Waves {
+            script: Handle::default(),
+        })
+        .insert_resource(GameRng::new(RunOptions::from_env().seed()))
+        .insert_resource(side_effects)
+        .insert_resource(boundaries)
+        .insert_resource(GameMode::default())
+        .insert_resource(RunOptions::default())
+        .insert_resource(RunModifiers::default())
+        .insert_resource(BossFight::new(&config))
+        .insert_resource(Combo::default())
+        .insert_resource(ScreenShake::default())
+        .insert_resource(WeaponState::new(&settings))
+        .insert_resource(InputLatency::default())
+        .insert_resource(PlayerInput::default())
+        .add_systems(
+            (
+                headless_input.before(player_movement).before(player_shoot),
+                spawner_system,
+                physics_objects,
+                cell_despawner,
+                player_bullet_despawner,
+                player_collisions,
+                player_bullet_collisions,
+                cell_cell_collisions.after(physics_objects),
+                player_movement,
+                player_shoot
+                    .after(player_bullet_despawner)
+                    .after(player_bullet_collisions),
+                shooter_germ_system,
+                enemy_bullet_collisions,
+                dying_cell_system,
+                spawn_protection_system,
+                telegraph_system,
+            )
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    app.world.spawn((
+        TransformBundle::default(),
+        Player::physics(&config),
+        Player::new(&settings, &config),
+    ));
+    let free = (0..config.bullet_pool_size)
+        .map(|_| app.world.spawn(BulletPool::bundle(&spawner)).id())
+        .collect();
+    app.insert_resource(BulletPool { free, spawned: 0 })
+        .insert_resource(spawner)
+        .insert_resource(settings)
+        .insert_resource(config);
+
+    // Step the clock by exactly one fixed update per frame, regardless of the real time taken.
+    let step = Duration::from_secs_f32(TIME_STEP);
+    let mut now = app.world.resource::<Time>().startup();
+    let mut cells = app.world.query_filtered::<(), With<Cell>>();
+    let mut slowest = Duration::ZERO;
+    let mut max_cells = 0;
+    let start = std::time::Instant::now();
+    for _ in 0..ticks {
+        now += step;
+        app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+        let tick_start = std::time::Instant::now();
+        app.update();
+        slowest = slowest.max(tick_start.elapsed());
+        max_cells = max_cells.max(cells.iter(&app.world).count());
+    }
+    let total = start.elapsed();
+    println!(
+        "{} ticks in {:.3} s, {:.3} ms per tick on average, slowest tick {:.3} ms",
+        ticks,
+        total.as_secs_f64(),
+        total.as_secs_f64() * 1000.0 / f64::from(ticks.max(1)),
+        slowest.as_secs_f64() * 1000.0
+    );
+    let scoreboard = app.world.resource::<Scoreboard>();
+    println!(
+        "Peak cell count {}, final score {}",
+        max_cells, scoreboard.score
+    );
+    println!(
+        "{} shots fired, {} bullet entities spawned beyond the pool",
+        scoreboard.shots_fired,
+        app.world.resource::<BulletPool>().spawned
+    );
+}
+
+/// Scripted input of the headless mode: keep shooting while sweeping from side to side.
+fn headless_input(mut input: ResMut<PlayerInput>, mut tick: Local<u32>) {
+    *tick += 1;
+    // Change direction every two seconds.
+    let right = ((*tick as f32 * TIME_STEP / 2.0) as u32).is_multiple_of(2);
+    *input = PlayerInput {
+        left: !right,
+        right,
+        shoot: true,
+        ..default()
+    };
+}
@@ -0,0 +1,1122 @@
+//! Physics step, collision response and the collision lab.
+
+use std::{collections::HashSet, time::Duration};
+
+use bevy::{input::InputSystem, math::vec2, prelude::*, window::PrimaryWindow};
+use rand::Rng;
+
+use crate::{
+    audio::SoundEvent,
+    components::{Cell, CellType, Physics},
+    config::GameConfig,
+    game::{Boundaries, GameRng, GameState, RunModifier, RunModifiers, Scoreboard},
+    player::{BulletPool, Player, PlayerBullet},
+    settings::Settings,
+    side_effects::{SideEffectType, SideEffects},
+    spawner::{Boss, EnemyBullet, Spawner, CELL_KILL_RADIUS, CELL_TEXTURE_RADIUS},
+    ui::{
+        spawn_floating_text, DebugOverlay, HitFlash, ScreenShake, TextStyles, WelcomeText,
+        PENALTY_TEXT_COLOR,
+    },
+};
+
+// Defines the amount of time that should elapse between each physics step.
+pub(crate) const TIME_STEP: f32 = 1.0 / 144.0;
+
+/// Movement and collisions of the physics objects, and the tools to debug them.
+pub(crate) struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FixedTime::new_from_secs(TIME_STEP))
+            .insert_resource(StepDebug::default())
+            .insert_resource(TimeScale::default())
+            .add_system(enter_collision_lab.in_schedule(OnEnter(GameState::CollisionLab)))
+            .add_system(exit_collision_lab.in_schedule(OnExit(GameState::CollisionLab)))
+            .add_systems(
+                (
+                    physics_objects.run_if(
+                        in_state(GameState::Running).or_else(in_state(GameState::CollisionLab)),
+                    ),
+                    player_collisions.run_if(in_state(GameState::Running)),
+                    player_bullet_collisions.run_if(in_state(GameState::Running)),
+                    cell_cell_collisions
+                        .after(physics_objects)
+                        .run_if(in_state(GameState::Running)),
+                    enemy_bullet_collisions.run_if(in_state(GameState::Running)),
+                    count_simulation_steps.run_if(in_state(GameState::Running)),
+                    collision_lab_collisions
+                        .after(physics_objects)
+                        .run_if(in_state(GameState::CollisionLab)),
+                )
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                step_debug_system
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(time_scale_system)
+            .add_system(collision_lab_input.run_if(in_state(GameState::CollisionLab)));
+    }
+}
+
+/// Body of the collision lab. It returns to `start` when the lab is reset.
+#[derive(Component)]
+struct LabBody {
+    start: Vec2,
+}
+
+#[derive(Component)]
+struct LabText;
+
+/// State of the collision lab.
+#[derive(Resource, Default)]
+struct CollisionLab {
+    /// Body being dragged and the point where the drag started.
+    drag: Option<(Entity, Vec2)>,
+    /// Total momentum and kinetic energy before and after the last collision.
+    last_collision: Option<((Vec2, f32), (Vec2, f32))>,
+}
+
+/// Launch velocity per unit of drag distance in the collision lab.
+const LAB_LAUNCH_SCALE: f32 = 3.0;
+
+/// Total momentum and kinetic energy of the given bodies.
+fn momentum_and_energy<'a>(bodies: impl IntoIterator<Item = &'a Physics>) -> (Vec2, f32) {
+    bodies
+        .into_iter()
+        .fold((Vec2::ZERO, 0.0), |(momentum, energy), physics| {
+            (
+                momentum + physics.velocity * physics.mass(),
+                energy + 0.5 * physics.mass() * physics.velocity.length_squared(),
+            )
+        })
+}
+
+/// Speed of the game clock relative to real time. Scaling the clock slows down or speeds up all
+/// motion and timers together, including the number of physics steps per second.
+#[derive(Resource)]
+pub(crate) struct TimeScale {
+    /// Game speed chosen in the settings, applied during runs.
+    pub(crate) game_speed: f32,
+    /// Slow motion while the patient is about to die, 1 otherwise.
+    pub(crate) last_breath: f32,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self {
+            game_speed: 1.0,
+            last_breath: 1.0,
+        }
+    }
+}
+
+impl TimeScale {
+    pub(crate) fn factor(&self) -> f32 {
+        self.game_speed * self.last_breath
+    }
+
+    pub(crate) fn in_last_breath(&self) -> bool {
+        self.last_breath < 1.0
+    }
+}
+
+/// Debug controls to pause the simulation and advance it one physics step at a time.
+/// Only available while the debug overlay is visible.
+#[derive(Resource, Default)]
+pub(crate) struct StepDebug {
+    pub(crate) paused: bool,
+    /// Number of physics steps simulated in the current run.
+    pub(crate) frame: u64,
+}
+
+impl StepDebug {
+    /// Period of the fixed schedule while paused, long enough to never elapse.
+    pub(crate) const PAUSED_PERIOD: Duration = Duration::from_secs(3600);
+}
+
+/// Player-Cell collisions.
+pub(crate) fn player_collisions(
+    mut player_query: Query<(&mut Transform, &mut Physics), With<Player>>,
+    mut cell_query: Query<(&mut Transform, &mut Physics, &mut Cell), Without<Player>>,
+    side_effects: Res<SideEffects>,
+    config: Res<GameConfig>,
+) {
+    let (mut player_transform, mut player_physics) = player_query.single_mut();
+
+    for (mut transform, mut physics, mut cell) in &mut cell_query {
+        if elastic_collision(
+            &mut player_transform,
+            &mut player_physics,
+            &mut transform,
+            &mut physics,
+            config.collision_push_factor,
+        ) && side_effects.player_effect() == SideEffectType::NoShooting
+        {
+            // Damage the cells by touching in no shooting mode
+            cell.target_radius -= config.player_collision_damage;
+        }
+    }
+}
+
+/// Player bullet and Cell collisions.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn player_bullet_collisions(
+    mut commands: Commands,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut bullet_query: Query<(Entity, &Transform, &Physics, &mut PlayerBullet), Without<Cell>>,
+    mut pool: ResMut<BulletPool>,
+    mut cell_query: Query<(
+        Entity,
+        &Transform,
+        &mut Physics,
+        &mut Cell,
+        Option<&Boss>,
+        Option<&mut HitFlash>,
+    )>,
+    side_effects: Res<SideEffects>,
+    run_modifiers: Res<RunModifiers>,
+    config: Res<GameConfig>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    let damage = if run_modifiers.has(RunModifier::HeavyRounds) {
+        config.bullet_damage * 2.0
+    } else {
+        config.bullet_damage
+    };
+    for (bullet_entity, bullet_transform, bullet_physics, mut bullet) in &mut bullet_query {
+        let bullet_radius = bullet.radius();
+        // Test the path covered in the last step, so that fast bullets can't skip over a cell.
+        let position = bullet_transform.translation.truncate();
+        let previous = position - bullet_physics.velocity * TIME_STEP;
+        let mut released = false;
+        for (cell_entity, cell_transform, mut cell_physics, mut cell, boss, hit_flash) in
+            &mut cell_query
+        {
+            // A released bullet must not hit the other cells it overlaps in the same step.
+            if released {
+                break;
+            }
+            let dist = segment_distance(previous, position, cell_transform.translation.truncate());
+            if dist <= bullet_radius + cell_physics.radius {
+                if bullet.hit.contains(&cell_entity) {
+                    continue;
+                }
+                if bullet.pierce == 0 {
+                    pool.release(&mut commands, bullet_entity);
+                    released = true;
+                } else {
+                    bullet.pierce -= 1;
+                    bullet.hit.push(cell_entity);
+                }
+                if cell.spawn_protection > 0.0 {
+                    continue;
+                }
+                let taken = if boss.is_some() {
+                    config.boss_damage_taken
+                } else {
+                    1.0
+                };
+                // The armor absorbs the hit, but the bullet still pushes the germ back.
+                let armored = match &mut cell.cell_type {
+                    CellType::ArmoredGerm { hits_remaining } if *hits_remaining > 0 => {
+                        *hits_remaining -= 1;
+                        true
+                    }
+                    _ => false,
+                };
+                if !armored {
+                    let alive = cell.target_radius >= CELL_KILL_RADIUS;
+                    cell.target_radius -=
+                        damage * bullet.kind.damage_multiplier() * bullet.power * taken;
+                    // Played on the fatal hit rather than once the germ has shrunk away.
+                    if alive && cell.target_radius < CELL_KILL_RADIUS && cell.cell_type.is_germ() {
+                        sound_events.send(SoundEvent::GermKilled);
+                    }
+                }
+                if let Some(mut hit_flash) = hit_flash {
+                    if matches!(cell.cell_type, CellType::ArmoredGerm { hits_remaining: 0 }) {
+                        hit_flash.tint = Color::WHITE;
+                    }
+                    hit_flash.trigger();
+                }
+                if !((bullet_transform.translation.x > side_effects.right_effect_x
+                    && side_effects.right_effect == SideEffectType::NoKnockback)
+                    || (bullet_transform.translation.x < side_effects.left_effect_x
+                        && side_effects.left_effect == SideEffectType::NoKnockback))
+                {
+                    cell_physics.apply_impulse(vec2(
+                        0.0,
+                        config.knockback_impulse * bullet.kind.knockback_multiplier(),
+                    ));
+                    cell_physics.acceleration.y -= 50.0;
+                }
+
+                if cell.cell_type.is_germ() && !bullet.hit_germ {
+                    bullet.hit_germ = true;
+                    scoreboard.shots_hit += 1;
+                }
+                if !armored && cell.cell_type.is_germ() {
+                    scoreboard.score += run_modifiers.score_multiplier();
+                }
+            }
+        }
+    }
+}
+
+/// Distance from `point` to the closest point of the segment between `start` and `end`.
+fn segment_distance(start: Vec2, end: Vec2, point: Vec2) -> f32 {
+    let segment = end - start;
+    let t = if segment == Vec2::ZERO {
+        0.0
+    } else {
+        ((point - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+    };
+    point.distance(start + segment * t)
+}
+
+fn vec_along(a: Vec2, b: Vec2) -> (Vec2, Vec2) {
+    let along = b * a.dot(b);
+    let not_along = a - along;
+    (along, not_along)
+}
+
+fn elastic_collision(
+    t1: &mut Transform,
+    p1: &mut Physics,
+    t2: &mut Transform,
+    p2: &mut Physics,
+    push_factor: f32,
+) -> bool {
+    let diff: Vec2 = (t1.translation - t2.translation).truncate();
+    let total_radius = p1.radius + p2.radius;
+    if diff.length_squared() > total_radius * total_radius {
+        return false;
+    }
+
+    let m1 = p1.mass();
+    let m2 = p2.mass();
+
+    // Solve velocity
+    let normal = diff.normalize_or_zero();
+    let (v1, w1) = vec_along(p1.velocity, normal);
+    let (v2, w2) = vec_along(p2.velocity, normal);
+    // v1i + v1f = v2i + v2f
+    // v1f = v2i + v2f - v1i
+    // Conservation of momentum
+    // m1 v1i + m2 v2i = m1 v1f + m2 v2f
+    // m1 v1i + m2 v2i = m1 (v2i + v2f - v1i) + m2 v2f
+    // m1 (v1i - v2i + v1i) + m2 v2i = m1 v2f + m2 v2f
+    // v2f = (m1 (v1i - v2i + v1i) + m2 v2i) / (m1 + m2)
+    let v2f = (m1 * (v1 + v1 - v2) + m2 * v2) / (m1 + m2);
+    let v1f = v2 + v2f - v1;
+    p1.velocity = v1f + w1;
+    p2.velocity = v2f + w2;
+
+    separate_overlap(t1, p1, t2, p2, push_factor);
+
+    true
+}
+
+/// Push two overlapping bodies apart. Each body is moved by `push_factor` times the overlap,
+/// so 0.5 resolves the overlap exactly. Returns false if they don't overlap.
+fn separate_overlap(
+    t1: &mut Transform,
+    p1: &Physics,
+    t2: &mut Transform,
+    p2: &Physics,
+    push_factor: f32,
+) -> bool {
+    let diff: Vec2 = (t1.translation - t2.translation).truncate();
+    let total_radius = p1.radius + p2.radius;
+    if diff.length_squared() > total_radius * total_radius {
+        return false;
+    }
+    let normal = diff.normalize_or_zero();
+    let push_length = (diff.length() - total_radius) * push_factor;
+    let push_x = normal.x * push_length;
+    let push_y = normal.y * push_length;
+    t1.translation.x -= push_x;
+    t1.translation.y -= push_y;
+    t2.translation.x += push_x;
+    t2.translation.y += push_y;
+    true
+}
+
+/// Pairs of cells close enough to collide. The playfield is divided into a uniform grid with
+/// buckets as wide as the largest cell, so only cells in neighboring buckets can touch. Cells
+/// outside the playfield go to the edge buckets. The pairs are in the order of `cells`, like
+/// `iter_combinations`.
+fn collision_pairs(
+    cells: &[(Entity, Vec2, f32)],
+    boundaries: &Boundaries,
+) -> Vec<(Entity, Entity)> {
+    let max_radius = cells
+        .iter()
+        .map(|(_, _, radius)| *radius)
+        .fold(0.0, f32::max);
+    let size = (max_radius * 2.0).max(1.0);
+    let columns = ((boundaries.right_wall - boundaries.left_wall) / size)
+        .ceil()
+        .max(1.0) as i32;
+    let rows = ((boundaries.top - boundaries.bottom) / size)
+        .ceil()
+        .max(1.0) as i32;
+    let bucket = |position: Vec2| {
+        (
+            (((position.x - boundaries.left_wall) / size) as i32).clamp(0, columns - 1),
+            (((position.y - boundaries.bottom) / size) as i32).clamp(0, rows - 1),
+        )
+    };
+    let mut grid = vec![Vec::new(); (columns * rows) as usize];
+    for (i, (_, position, _)) in cells.iter().enumerate() {
+        let (x, y) = bucket(*position);
+        grid[(y * columns + x) as usize].push(i);
+    }
+    let mut pairs = Vec::new();
+    for (i, (_, position, _)) in cells.iter().enumerate() {
+        let (x, y) = bucket(*position);
+        for ny in (y - 1).max(0)..=(y + 1).min(rows - 1) {
+            for nx in (x - 1).max(0)..=(x + 1).min(columns - 1) {
+                let neighbors = &grid[(ny * columns + nx) as usize];
+                pairs.extend(neighbors.iter().filter(|j| **j > i).map(|j| (i, *j)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+        .into_iter()
+        .map(|(i, j)| (cells[i].0, cells[j].0))
+        .collect()
+}
+
+pub(crate) fn cell_cell_collisions(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform, &mut Physics, &mut Cell)>,
+    mut game_rng: ResMut<GameRng>,
+    config: Res<GameConfig>,
+    boundaries: Res<Boundaries>,
+    mut contacts: Local<HashSet<(Entity, Entity)>>,
+) {
+    let cells = |query: &Query<(Entity, &mut Transform, &mut Physics, &mut Cell)>| {
+        query
+            .iter()
+            .map(|(entity, transform, physics, _)| {
+                (entity, transform.translation.truncate(), physics.radius)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut merged = Vec::new();
+    let mut touching = HashSet::new();
+    for (e1, e2) in collision_pairs(&cells(&query), &boundaries) {
+        if merged.contains(&e1) || merged.contains(&e2) {
+            continue;
+        }
+        let Ok([(_, mut t1, mut p1, mut c1), (_, mut t2, mut p2, mut c2)]) =
+            query.get_many_mut([e1, e2])
+        else {
+            continue;
+        };
+        let relative_speed = (p1.velocity - p2.velocity).length();
+        if !elastic_collision(
+            &mut t1,
+            &mut p1,
+            &mut t2,
+            &mut p2,
+            config.collision_push_factor,
+        ) {
+            continue;
+        }
+        // Merging is rolled once per contact, so cells resting on each other don't merge sooner
+        // the more physics steps they spend touching.
+        let pair = (e1.min(e2), e1.max(e2));
+        let new_contact = !contacts.contains(&pair);
+        touching.insert(pair);
+        if std::mem::discriminant(&c1.cell_type) != std::mem::discriminant(&c2.cell_type) {
+            // Cells have different types
+            c1.target_radius -= config.cell_intercollision_damage;
+            c2.target_radius -= config.cell_intercollision_damage;
+        } else if relative_speed < config.cell_merge_max_speed
+            && p1.radius.hypot(p2.radius) <= config.cell_merge_max_radius
+            // Cells that are being destroyed can't merge.
+            && c1.target_radius > 0.0
+            && c2.target_radius > 0.0
+            && new_contact
+            && game_rng.rng.gen_bool(config.cell_merge_chance)
+        {
+            merge_cells(&mut t1, &mut p1, &mut c1, &t2, &p2, &c2);
+            commands.entity(e2).despawn();
+            merged.push(e2);
+        }
+    }
+    // Pairs that stopped touching roll again on their next contact.
+    *contacts = touching;
+    // Extra passes only resolve positions, which keeps dense clusters from jittering.
+    for _ in 1..config.collision_iterations {
+        for (e1, e2) in collision_pairs(&cells(&query), &boundaries) {
+            if merged.contains(&e1) || merged.contains(&e2) {
+                continue;
+            }
+            if let Ok([(_, mut t1, p1, _), (_, mut t2, p2, _)]) = query.get_many_mut([e1, e2]) {
+                separate_overlap(&mut t1, &p1, &mut t2, &p2, config.collision_push_factor);
+            }
+        }
+    }
+}
+
+/// Merge the second cell into the first one, conserving mass and momentum.
+fn merge_cells(
+    t1: &mut Transform,
+    p1: &mut Physics,
+    c1: &mut Cell,
+    t2: &Transform,
+    p2: &Physics,
+    c2: &Cell,
+) {
+    let m1 = p1.mass();
+    let m2 = p2.mass();
+    let total_mass = m1 + m2;
+    p1.velocity = (p1.velocity * m1 + p2.velocity * m2) / total_mass;
+    let center = (t1.translation.truncate() * m1 + t2.translation.truncate() * m2) / total_mass;
+    t1.translation.x = center.x;
+    t1.translation.y = center.y;
+    // Mass is proportional to the area.
+    p1.radius = p1.radius.hypot(p2.radius);
+    c1.target_radius = c1.target_radius.hypot(c2.target_radius);
+    c1.top_bound = c1.top_bound.max(c2.top_bound);
+    c1.full_radius = c1.full_radius.hypot(c2.full_radius);
+    if let (
+        CellType::Body { patient_hp },
+        CellType::Body {
+            patient_hp: other_hp,
+        },
+    ) = (&mut c1.cell_type, &c2.cell_type)
+    {
+        *patient_hp += other_hp;
+    }
+}
+
+/// Pause with F5 and step with F6, in debug builds only. Runs before the fixed schedule, which
+/// is controlled by replacing its timer: a paused timer never elapses, and a step elapses exactly
+/// one period.
+fn step_debug_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    debug_overlay: Res<DebugOverlay>,
+    mut step_debug: ResMut<StepDebug>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    let was_paused = step_debug.paused;
+    if cfg!(debug_assertions) && debug_overlay.visible && keyboard_input.just_pressed(KeyCode::F5) {
+        step_debug.paused = !step_debug.paused;
+    }
+    if !debug_overlay.visible {
+        step_debug.paused = false;
+    }
+    if step_debug.paused {
+        let mut paused_time = FixedTime::new(StepDebug::PAUSED_PERIOD);
+        if keyboard_input.just_pressed(KeyCode::F6) {
+            paused_time.tick(StepDebug::PAUSED_PERIOD);
+        }
+        *fixed_time = paused_time;
+    } else if was_paused {
+        *fixed_time = FixedTime::new_from_secs(TIME_STEP);
+    }
+}
+
+fn time_scale_system(
+    game_state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    mut time_scale: ResMut<TimeScale>,
+    mut time: ResMut<Time>,
+) {
+    // Menus always run at normal speed.
+    if game_state.0 == GameState::Running {
+        time_scale.game_speed = settings.game_speed;
+    } else {
+        time_scale.game_speed = 1.0;
+        time_scale.last_breath = 1.0;
+    }
+    let factor = time_scale.factor();
+    if time.relative_speed() != factor {
+        time.set_relative_speed(factor);
+    }
+}
+
+fn count_simulation_steps(mut step_debug: ResMut<StepDebug>) {
+    step_debug.frame += 1;
+}
+
+/// Update physics objects.
+pub(crate) fn physics_objects(
+    boundaries: Res<Boundaries>,
+    side_effects: Res<SideEffects>,
+    config: Res<GameConfig>,
+    mut query: Query<(&mut Transform, &mut Physics, Option<&Player>)>,
+) {
+    for (mut transform, mut physics, player) in &mut query {
+        let mut velocity_mul = TIME_STEP;
+        if player.is_some() {
+            velocity_mul *= side_effects.player_effect().movement_multiplier();
+        } else if config.zone_movement_affects_cells {
+            if transform.translation.x > side_effects.right_effect_x {
+                velocity_mul *= side_effects.right_effect.movement_multiplier();
+            } else if transform.translation.x < side_effects.left_effect_x {
+                velocity_mul *= side_effects.left_effect.movement_multiplier();
+            }
+        }
+        physics.velocity.x += physics.acceleration.x * TIME_STEP;
+        physics.velocity.y += physics.acceleration.y * TIME_STEP;
+        transform.translation.x += physics.velocity.x * velocity_mul;
+        transform.translation.y += physics.velocity.y * velocity_mul;
+
+        let radius = physics.radius;
+        let wall_bounce = if player.is_none() && config.cells_stop_at_walls {
+            0.0
+        } else {
+            -physics.elasticity
+        };
+        if transform.translation.x - radius < boundaries.left_wall {
+            transform.translation.x = boundaries.left_wall + radius;
+            physics.velocity.x *= wall_bounce;
+        } else if transform.translation.x + radius > boundaries.right_wall {
+            transform.translation.x = boundaries.right_wall - radius;
+            physics.velocity.x *= wall_bounce;
+        }
+    }
+}
+
+/// Enemy bullets hitting the player, and the ones that left the screen.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn enemy_bullet_collisions(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    config: Res<GameConfig>,
+    text_styles: Res<TextStyles>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut shake: ResMut<ScreenShake>,
+    bullet_query: Query<(Entity, &Transform, &Physics), With<EnemyBullet>>,
+    player_query: Query<(&Transform, &Physics), (With<Player>, Without<EnemyBullet>)>,
+) {
+    let (player_transform, player_physics) = player_query.single();
+    let player_position = player_transform.translation.truncate();
+    for (entity, transform, physics) in &bullet_query {
+        let position = transform.translation.truncate();
+        if position.y < boundaries.bottom - physics.radius
+            || position.y > boundaries.top + physics.radius
+        {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let total_radius = physics.radius + player_physics.radius;
+        if position.distance_squared(player_position) > total_radius * total_radius {
+            continue;
+        }
+        commands.entity(entity).despawn();
+        scoreboard.patient_hp -= config.enemy_bullet_damage;
+        shake.add_trauma(0.3);
+        spawn_floating_text(
+            &mut commands,
+            &text_styles,
+            position,
+            format!("-{} HP", config.enemy_bullet_damage),
+            PENALTY_TEXT_COLOR,
+            1.0,
+        );
+    }
+}
+
+fn enter_collision_lab(
+    mut commands: Commands,
+    spawner: Res<Spawner>,
+    text_styles: Res<TextStyles>,
+    welcome_query: Query<Entity, With<WelcomeText>>,
+) {
+    // The welcome screen is rebuilt when the lab is left.
+    for entity in &welcome_query {
+        commands.entity(entity).despawn();
+    }
+    commands.insert_resource(CollisionLab::default());
+    for (start, radius, texture) in [
+        (vec2(-150.0, 0.0), 45.0, spawner.blood_texture.clone()),
+        (vec2(150.0, 0.0), 30.0, spawner.germ_texture.clone()),
+    ] {
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_translation(start.extend(1.0))
+                    .with_scale(Vec3::splat(radius / CELL_TEXTURE_RADIUS)),
+                texture,
+                ..default()
+            },
+            Physics {
+                velocity: Vec2::ZERO,
+                acceleration: Vec2::ZERO,
+                elasticity: 0.9,
+                radius,
+            },
+            LabBody { start },
+        ));
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 22.0,
+                ..text_styles.label_style.clone()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(8.0),
+                left: Val::Px(8.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LabText,
+    ));
+}
+
+#[allow(clippy::type_complexity)]
+fn exit_collision_lab(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<LabBody>, With<LabText>)>>,
+) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<CollisionLab>();
+}
+
+/// Drag a body with the mouse and release it to launch it away from the cursor.
+#[allow(clippy::too_many_arguments)]
+fn collision_lab_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut lab: ResMut<CollisionLab>,
+    mut body_query: Query<(Entity, &mut Transform, &mut Physics, &LabBody)>,
+    mut text_query: Query<&mut Text, With<LabText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Init);
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::R) {
+        for (_, mut transform, mut physics, body) in &mut body_query {
+            transform.translation = body.start.extend(transform.translation.z);
+            physics.velocity = Vec2::ZERO;
+        }
+        lab.drag = None;
+        lab.last_collision = None;
+    }
+    let cursor = window_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .and_then(|cursor| {
+            let (camera, transform) = camera_query.get_single().ok()?;
+            camera.viewport_to_world(transform, cursor)
+        })
+        .map(|ray| ray.origin.truncate());
+    if let Some(point) = cursor {
+        if mouse_input.just_pressed(MouseButton::Left) {
+            lab.drag = None;
+            for (entity, transform, mut physics, _) in &mut body_query {
+                if transform.translation.truncate().distance(point) <= physics.radius {
+                    // Hold the body still while aiming.
+                    physics.velocity = Vec2::ZERO;
+                    lab.drag = Some((entity, point));
+                    break;
+                }
+            }
+        }
+        if mouse_input.just_released(MouseButton::Left) {
+            if let Some((entity, start)) = lab.drag.take() {
+                if let Ok((_, _, mut physics, _)) = body_query.get_mut(entity) {
+                    physics.velocity = (start - point) * LAB_LAUNCH_SCALE;
+                }
+            }
+        }
+    }
+
+    let Ok(mut text) = text_query.get_single_mut() else {
+        return;
+    };
+    let mut value =
+        "COLLISION LAB\nDrag a body and release to launch it. R: Reset, ESC: Exit\n".to_owned();
+    for (i, (_, _, physics, _)) in body_query.iter().enumerate() {
+        value += &format!(
+            "\nBody {}: mass {:.0}, velocity ({:.0}, {:.0})",
+            i + 1,
+            physics.mass(),
+            physics.velocity.x,
+            physics.velocity.y
+        );
+    }
+    let (momentum, energy) = momentum_and_energy(body_query.iter().map(|(_, _, p, _)| p));
+    value += &format!(
+        "\nTotal momentum: ({:.0}, {:.0}), energy: {:.0}",
+        momentum.x, momentum.y, energy
+    );
+    if let Some(((momentum_before, energy_before), (momentum_after, energy_after))) =
+        lab.last_collision
+    {
+        value += &format!(
+            "\n\nLast collision\nMomentum: ({:.0}, {:.0}) -> ({:.0}, {:.0})\nEnergy: {:.0} -> {:.0}",
+            momentum_before.x,
+            momentum_before.y,
+            momentum_after.x,
+            momentum_after.y,
+            energy_before,
+            energy_after
+        );
+    }
+    text.sections[0].value = value;
+}
+
+/// Collide the lab bodies and record the momentum and energy around each collision.
+fn collision_lab_collisions(
+    config: Res<GameConfig>,
+    mut lab: ResMut<CollisionLab>,
+    mut query: Query<(&mut Transform, &mut Physics), With<LabBody>>,
+) {
+    let mut combinations = query.iter_combinations_mut();
+    while let Some([(mut t1, mut p1), (mut t2, mut p2)]) = combinations.fetch_next() {
+        let before = momentum_and_energy([&*p1, &*p2]);
+        if elastic_collision(
+            &mut t1,
+            &mut p1,
+            &mut t2,
+            &mut p2,
+            config.collision_push_factor,
+        ) {
+            lab.last_collision = Some((before, momentum_and_energy([&*p1, &*p2])));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::player::BulletKind;
+
+    /// App running [`physics_objects`] on each update, on the default playfield.
+    fn physics_app(config: GameConfig) -> App {
+        let mut app = App::new();
+        app.insert_resource(Boundaries::default())
+            .insert_resource(SideEffects::new(&config))
+            .insert_resource(config)
+            .add_system(physics_objects);
+        app
+    }
+
+    #[test]
+    fn player_stops_at_wall_without_bounce() {
+        let config = GameConfig {
+            player_wall_elasticity: 0.0,
+            ..default()
+        };
+        let wall = Boundaries::default().right_wall;
+        let elasticity = config.player_wall_elasticity;
+        let mut app = physics_app(config);
+        let entity = app
+            .world
+            .spawn((
+                Transform::from_xyz(wall - 15.0, 0.0, 0.0),
+                Physics {
+                    velocity: vec2(500.0, 0.0),
+                    acceleration: Vec2::ZERO,
+                    elasticity,
+                    radius: 15.0,
+                },
+            ))
+            .id();
+        app.update();
+        let physics = app.world.get::<Physics>(entity).unwrap();
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        assert_eq!(physics.velocity.x, 0.0);
+        assert_eq!(transform.translation.x, wall - physics.radius);
+    }
+
+    #[test]
+    fn merge_conserves_mass_and_momentum() {
+        let mut p1 = Physics {
+            velocity: vec2(40.0, -10.0),
+            ..Physics::new(30.0)
+        };
+        let mut c1 = Cell::new(CellType::Body { patient_hp: 3 }, 30.0);
+        let p2 = Physics {
+            velocity: vec2(-20.0, -50.0),
+            ..Physics::new(40.0)
+        };
+        let c2 = Cell::new(CellType::Body { patient_hp: 4 }, 40.0);
+        let mut t1 = Transform::from_xyz(0.0, 0.0, 0.0);
+        let t2 = Transform::from_xyz(70.0, 0.0, 0.0);
+        let mass = p1.mass() + p2.mass();
+        let momentum = p1.velocity * p1.mass() + p2.velocity * p2.mass();
+        merge_cells(&mut t1, &mut p1, &mut c1, &t2, &p2, &c2);
+        assert!((p1.mass() - mass).abs() < 0.01);
+        assert!((p1.velocity * p1.mass() - momentum).length() < 0.1);
+        assert_eq!(p1.radius, 50.0);
+        assert!(matches!(c1.cell_type, CellType::Body { patient_hp: 7 }));
+    }
+
+    #[test]
+    fn lasting_contact_rolls_to_merge_only_once() {
+        let merges = (0..20)
+            .filter(|&seed| {
+                let mut app = App::new();
+                app.insert_resource(Boundaries::default())
+                    .insert_resource(GameRng::new(seed))
+                    .insert_resource(GameConfig {
+                        cell_merge_chance: 0.5,
+                        ..default()
+                    })
+                    .add_system(cell_cell_collisions);
+                let cells: Vec<Entity> = (0..2)
+                    .map(|_| {
+                        let physics = Physics::new(20.0);
+                        let cell = Cell::new(CellType::Body { patient_hp: 1 }, 20.0);
+                        app.world.spawn((Transform::default(), physics, cell)).id()
+                    })
+                    .collect();
+                // Keep pressing the cells together, as if they were resting on each other.
+                for _ in 0..50 {
+                    for (cell, x) in cells.iter().zip([-15.0, 15.0]) {
+                        let Some(mut entity) = app.world.get_entity_mut(*cell) else {
+                            return true;
+                        };
+                        *entity.get_mut::<Transform>().unwrap() = Transform::from_xyz(x, 0.0, 0.0);
+                        entity.get_mut::<Physics>().unwrap().velocity = Vec2::ZERO;
+                    }
+                    app.update();
+                }
+                false
+            })
+            .count();
+        // Rolling on every step would merge all of them.
+        assert!(merges > 0);
+        assert!(merges < 20);
+    }
+
+    #[test]
+    fn stacked_cells_settle_without_jitter_or_tunneling() {
+        let config = GameConfig {
+            collision_iterations: 8,
+            cell_merge_chance: 0.0,
+            ..default()
+        };
+        let mut app = App::new();
+        app.insert_resource(Boundaries::default())
+            .insert_resource(GameRng::new(7))
+            .insert_resource(config)
+            .add_system(cell_cell_collisions);
+        // A column of cells, each overlapping the next one by half its radius.
+        let cells: Vec<Entity> = (0..6)
+            .map(|i| {
+                let physics = Physics::new(30.0);
+                let cell = Cell::new(CellType::Body { patient_hp: 1 }, 30.0);
+                let transform = Transform::from_xyz(0.0, i as f32 * 45.0, 0.0);
+                app.world.spawn((transform, physics, cell)).id()
+            })
+            .collect();
+        let heights = |app: &App| -> Vec<f32> {
+            cells
+                .iter()
+                .map(|&cell| app.world.get::<Transform>(cell).unwrap().translation.y)
+                .collect()
+        };
+        for _ in 0..50 {
+            app.update();
+        }
+        let settled = heights(&app);
+        for _ in 0..10 {
+            app.update();
+        }
+        for (before, after) in settled.iter().zip(heights(&app)) {
+            assert!((after - before).abs() < 0.01);
+        }
+        for pair in settled.windows(2) {
+            // Still in order and no longer overlapping noticeably.
+            assert!(pair[1] - pair[0] > 59.0);
+        }
+    }
+
+    #[test]
+    fn bullets_dont_damage_protected_cells() {
+        let mut app = App::new();
+        app.insert_resource(Scoreboard::default())
+            .insert_resource(SideEffects::new(&GameConfig::default()))
+            .insert_resource(RunModifiers::default())
+            .insert_resource(GameConfig::default())
+            .insert_resource(BulletPool::default())
+            .add_event::<SoundEvent>()
+            .add_system(player_bullet_collisions);
+        let mut spawn_target = |spawn_protection| {
+            let physics = Physics::new(30.0);
+            let mut cell = Cell::new(CellType::Germ, 30.0);
+            cell.spawn_protection = spawn_protection;
+            let x = if spawn_protection > 0.0 {
+                -100.0
+            } else {
+                100.0
+            };
+            app.world.spawn((
+                Transform::from_xyz(x, 0.0, 0.0),
+                Physics {
+                    velocity: vec2(0.0, 1.0),
+                    acceleration: Vec2::ZERO,
+                    elasticity: 1.0,
+                    radius: 2.0,
+                },
+                PlayerBullet::new(BulletKind::Normal, vec2(x, -50.0), 0),
+            ));
+            app.world
+                .spawn((Transform::from_xyz(x, 0.0, 0.0), physics, cell))
+                .id()
+        };
+        let protected = spawn_target(1.0);
+        let unprotected = spawn_target(0.0);
+        app.update();
+        let target_radius = |cell| app.world.get::<Cell>(cell).unwrap().target_radius;
+        assert_eq!(target_radius(protected), 30.0);
+        assert!(target_radius(unprotected) < 30.0);
+    }
+
+    #[test]
+    fn cells_stop_at_walls_without_bounce() {
+        let mut app = physics_app(GameConfig {
+            cells_stop_at_walls: true,
+            ..default()
+        });
+        let physics = Physics {
+            velocity: vec2(-300.0, -50.0),
+            ..Physics::new(30.0)
+        };
+        let cell = Cell::new(CellType::Body { patient_hp: 1 }, 30.0);
+        let wall = Boundaries::default().left_wall;
+        let entity = app
+            .world
+            .spawn((Transform::from_xyz(wall + 31.0, 0.0, 0.0), physics, cell))
+            .id();
+        app.update();
+        let physics = app.world.get::<Physics>(entity).unwrap();
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        assert_eq!(physics.velocity.x, 0.0);
+        assert_eq!(physics.velocity.y, -50.0);
+        assert_eq!(transform.translation.x, wall + 30.0);
+    }
+
+    #[test]
+    fn zone_slowdown_can_spare_the_cells() {
+        let mut side_effects = SideEffects {
+            right_effect: SideEffectType::SlowerMovement,
+            ..SideEffects::new(&GameConfig::default())
+        };
+        let x = side_effects.right_effect_x + 100.0;
+        side_effects.update_player_zones(x, 0.0);
+        let mut app = physics_app(GameConfig {
+            zone_movement_affects_cells: false,
+            ..default()
+        });
+        app.insert_resource(side_effects);
+        let player = app
+            .world
+            .spawn((
+                Transform::from_xyz(x, 0.0, 0.0),
+                Physics {
+                    velocity: vec2(0.0, 100.0),
+                    acceleration: Vec2::ZERO,
+                    elasticity: 0.0,
+                    radius: 15.0,
+                },
+                Player::new(&Settings::default(), &GameConfig::default()),
+            ))
+            .id();
+        let physics = Physics {
+            velocity: vec2(0.0, 100.0),
+            ..Physics::new(30.0)
+        };
+        let cell = Cell::new(CellType::Body { patient_hp: 1 }, 30.0);
+        let cell = app
+            .world
+            .spawn((Transform::from_xyz(x, 0.0, 0.0), physics, cell))
+            .id();
+        app.update();
+        let distance = |entity| app.world.get::<Transform>(entity).unwrap().translation.y;
+        assert_eq!(distance(cell), 100.0 * TIME_STEP);
+        assert!(distance(player) < distance(cell));
+    }
+
+    #[test]
+    fn game_speed_scales_the_distance_per_real_second() {
+        let distance_in_one_second = |game_speed| {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+                .add_state::<GameState>()
+                .insert_resource(FixedTime::new_from_secs(TIME_STEP))
+                .insert_resource(TimeScale::default())
+                .insert_resource(Settings {
+                    game_speed,
+                    ..default()
+                })
+                .insert_resource(Boundaries::default())
+                .insert_resource(SideEffects::new(&GameConfig::default()))
+                .insert_resource(GameConfig::default())
+                .add_system(time_scale_system)
+                .add_system(physics_objects.in_schedule(CoreSchedule::FixedUpdate));
+            app.world
+                .resource_mut::<NextState<GameState>>()
+                .set(GameState::Running);
+            let physics = Physics {
+                velocity: vec2(0.0, -100.0),
+                ..Physics::new(30.0)
+            };
+            let cell = Cell::new(CellType::Body { patient_hp: 1 }, 30.0);
+            let entity = app.world.spawn((Transform::default(), physics, cell)).id();
+            let mut now = app.world.resource::<Time>().startup();
+            let mut frame = |app: &mut App| {
+                now += Duration::from_secs_f32(1.0 / 60.0);
+                app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+                app.update();
+            };
+            // Enter the run and apply its speed first.
+            for _ in 0..2 {
+                frame(&mut app);
+            }
+            let start = app.world.get::<Transform>(entity).unwrap().translation.y;
+            for _ in 0..60 {
+                frame(&mut app);
+            }
+            start - app.world.get::<Transform>(entity).unwrap().translation.y
+        };
+        let ratio = distance_in_one_second(0.5) / distance_in_one_second(1.0);
+        assert!((ratio - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn collisions_conserve_momentum() {
+        let physics = |radius, velocity| Physics {
+            velocity,
+            ..Physics::new(radius)
+        };
+        let mut small = physics(20.0, vec2(120.0, 30.0));
+        let mut large = physics(60.0, vec2(-40.0, -10.0));
+        let momentum = |a: &Physics, b: &Physics| a.velocity * a.mass() + b.velocity * b.mass();
+        let before = momentum(&small, &large);
+        let mut t1 = Transform::from_xyz(0.0, 0.0, 0.0);
+        let mut t2 = Transform::from_xyz(70.0, 20.0, 0.0);
+        assert!(elastic_collision(
+            &mut t1, &mut small, &mut t2, &mut large, 0.5
+        ));
+        assert!(small.velocity.x < 0.0);
+        assert!((momentum(&small, &large) - before).length() < before.length() * 1e-5);
+    }
+}
@@ -0,0 +1,1143 @@
+//! The player's controls, weapons, replays and ghost.
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{
+    input::InputSystem, math::vec2, prelude::*, sprite::MaterialMesh2dBundle, window::PrimaryWindow,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::SoundEvent,
+    components::{Cell, Physics},
+    config::{save_ron, GameConfig},
+    game::{
+        apply_run_modifiers, start_game, starting_run, Boundaries, Fade, GameMode, GameRng,
+        GameState, RunModifier, RunModifiers, RunOptions, Scoreboard,
+    },
+    physics::{physics_objects, player_bullet_collisions, TimeScale, TIME_STEP},
+    settings::{KeyBindings, Settings, ShootMode},
+    side_effects::{MissedShotRisk, SideEffectType, SideEffectUpdateEvent, SideEffects, SideFx},
+    spawner::{Spawner, CURE_COLOR, HEALTH_PICKUP_COLOR},
+    ui::{spawn_floating_text, DebugOverlay, TextStyles, Tip, TipEvent, COMBO_TEXT_COLOR},
+};
+
+pub(crate) const NANO_COLOR: Color = Color::rgb(1.0, 0.8, 0.8);
+
+/// Player movement, weapons and pickups.
+pub(crate) struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Ghosts::default())
+            .insert_resource(InputLatency::default())
+            .insert_resource(WeaponState::default())
+            .insert_resource(PlayerInput::default())
+            .insert_resource(ReplayRecorder::from_env())
+            .add_system(
+                start_replay
+                    .run_if(starting_run)
+                    .before(start_game)
+                    .before(apply_run_modifiers)
+                    .in_schedule(OnEnter(GameState::Running)),
+            )
+            .add_system(save_replay.in_schedule(OnEnter(GameState::Ended)))
+            .add_systems(
+                (
+                    read_player_input
+                        .before(player_movement)
+                        .before(player_shoot)
+                        .run_if(in_state(GameState::Running)),
+                    // Bullets released this step can be reused right away.
+                    player_shoot
+                        .after(player_bullet_despawner)
+                        .after(player_bullet_collisions)
+                        .run_if(in_state(GameState::Running)),
+                    player_bullet_despawner.run_if(in_state(GameState::Running)),
+                    player_movement.run_if(in_state(GameState::Running)),
+                    pickup_system.run_if(in_state(GameState::Running)),
+                    player_energy_system.run_if(in_state(GameState::Running)),
+                    last_breath_system.run_if(in_state(GameState::Running)),
+                    player_zone_system
+                        .before(physics_objects)
+                        .run_if(in_state(GameState::Running)),
+                    ghost_system
+                        .after(player_movement)
+                        .run_if(in_state(GameState::Running)),
+                )
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(weapon_upgrade_system.run_if(in_state(GameState::Running)))
+            .add_system(apply_player_skin)
+            .add_system(
+                fire_press_system
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            );
+    }
+}
+
+/// Identifies runs that play out the same way, so a ghost can follow the same course.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub(crate) struct GhostKey {
+    pub(crate) mode: GameMode,
+    pub(crate) seed: u64,
+    pub(crate) modifiers: Vec<RunModifier>,
+}
+
+impl GhostKey {
+    pub(crate) fn new(mode: GameMode, seed: u64, run_modifiers: &RunModifiers) -> Self {
+        Self {
+            mode,
+            seed,
+            modifiers: run_modifiers.active.clone(),
+        }
+    }
+}
+
+/// Player path of a finished run.
+pub(crate) struct GhostRun {
+    pub(crate) score: usize,
+    /// Player position at each fixed update tick.
+    pub(crate) positions: Vec<Vec2>,
+}
+
+/// Player paths of the best runs in this session, replayed as ghosts in runs with the same seed.
+#[derive(Resource, Default)]
+pub(crate) struct Ghosts {
+    pub(crate) best: HashMap<GhostKey, GhostRun>,
+    /// Path of the current run so far.
+    pub(crate) current: Vec<Vec2>,
+}
+
+impl Ghosts {
+    /// Keep the path of the current run if it beats the best run with the same key.
+    pub(crate) fn finish_run(&mut self, key: GhostKey, score: usize) {
+        let positions = std::mem::take(&mut self.current);
+        if self.best.get(&key).is_none_or(|best| score > best.score) {
+            self.best.insert(key, GhostRun { score, positions });
+        }
+    }
+}
+
+/// Translucent sprite following the path of the best run.
+#[derive(Component)]
+pub(crate) struct Ghost;
+
+/// Player input of a single physics step, read from the keyboard or a replay.
+#[derive(Resource, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub(crate) struct PlayerInput {
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+    pub(crate) up: bool,
+    pub(crate) down: bool,
+    pub(crate) shoot: bool,
+    pub(crate) secondary: bool,
+    /// World position of the cursor, only read for aimed shots.
+    pub(crate) aim: Option<Vec2>,
+}
+
+/// Options and input of a recorded run. The gameplay settings aren't recorded, so a replay only
+/// plays out the same way with the settings it was recorded with.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Replay {
+    pub(crate) seed: u64,
+    pub(crate) mode: GameMode,
+    pub(crate) modifiers: Vec<RunModifier>,
+    pub(crate) practice_score: usize,
+    /// Player input at each fixed update tick.
+    pub(crate) inputs: Vec<PlayerInput>,
+}
+
+/// Records the input of each run, or plays back the replay given with `--replay <file>`.
+#[derive(Resource, Default)]
+pub(crate) struct ReplayRecorder {
+    pub(crate) recording: Replay,
+    pub(crate) playback: Option<Replay>,
+    /// Fixed update tick of the playback.
+    pub(crate) tick: usize,
+}
+
+impl ReplayRecorder {
+    /// The last finished run is saved here.
+    pub(crate) const FILE_NAME: &str = "replay.ron";
+
+    pub(crate) fn from_env() -> Self {
+        let playback = std::env::args()
+            .skip_while(|arg| arg != "--replay")
+            .nth(1)
+            .and_then(|path| {
+                let result = std::fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|contents| ron::from_str(&contents).map_err(|err| err.to_string()));
+                result
+                    .map_err(|err| warn!("Failed to load replay {}: {}", path, err))
+                    .ok()
+            });
+        Self {
+            playback,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct Player {
+    pub(crate) shoot_timer: Timer,
+    /// Cooldown of the secondary weapon.
+    pub(crate) secondary_timer: Timer,
+    /// Spent by shooting, see [`GameConfig::shot_energy_cost`].
+    pub(crate) energy: f32,
+    /// Seconds spent pinned against the top edge.
+    pub(crate) camping_time: f32,
+    /// Seconds the fire key has been held for a charged shot, `None` while it's released.
+    pub(crate) charge: Option<f32>,
+    /// Raised by shooting, see [`GameConfig::shot_heat`].
+    pub(crate) heat: f32,
+    /// Shooting is blocked until the heat drops to [`GameConfig::overheat_recovery`].
+    pub(crate) overheated: bool,
+}
+
+impl Player {
+    pub(crate) fn new(settings: &Settings, config: &GameConfig) -> Self {
+        Self {
+            shoot_timer: Timer::from_seconds(0.25, TimerMode::Once),
+            secondary_timer: Timer::from_seconds(
+                settings.secondary_weapon.cooldown(),
+                TimerMode::Once,
+            ),
+            energy: config.max_energy,
+            camping_time: 0.0,
+            charge: None,
+            heat: 0.0,
+            overheated: false,
+        }
+    }
+
+    pub(crate) fn physics(config: &GameConfig) -> Physics {
+        Physics {
+            velocity: Vec2::ZERO,
+            acceleration: Vec2::ZERO,
+            elasticity: config.player_wall_elasticity,
+            radius: 15.0,
+        }
+    }
+
+    pub(crate) fn add_heat(&mut self, heat: f32) {
+        self.heat = (self.heat + heat).min(1.0);
+        if heat > 0.0 && self.heat >= 1.0 {
+            self.overheated = true;
+        }
+    }
+
+    /// Spend the energy for a shot. Returns false without spending if there's not enough.
+    pub(crate) fn try_spend_energy(&mut self, cost: f32) -> bool {
+        if self.energy < cost {
+            return false;
+        }
+        self.energy -= cost;
+        true
+    }
+}
+
+/// Upgrades of the player's bullets earned during the run.
+#[derive(Resource, Default)]
+pub(crate) struct WeaponState {
+    /// Number of cells each bullet passes through before despawning.
+    pub(crate) pierce: u8,
+    /// Number of bullets in each primary shot, see [`Settings::spread`].
+    pub(crate) spread: u8,
+}
+
+impl WeaponState {
+    pub(crate) fn new(settings: &Settings) -> Self {
+        Self {
+            pierce: 0,
+            spread: settings.spread.max(1),
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct PlayerBullet {
+    pub(crate) kind: BulletKind,
+    /// Position the bullet was fired from, to limit the range of short-range bullets.
+    pub(crate) origin: Vec2,
+    /// Size and damage relative to the bullet kind, raised by charging.
+    pub(crate) power: f32,
+    /// Number of cells the bullet can pass through before despawning.
+    pub(crate) pierce: u8,
+    /// Cells already hit by a piercing bullet, which it can't damage again.
+    pub(crate) hit: Vec<Entity>,
+    /// Set on the first germ hit, so piercing shots count once for accuracy.
+    pub(crate) hit_germ: bool,
+}
+
+impl PlayerBullet {
+    pub(crate) fn new(kind: BulletKind, origin: Vec2, pierce: u8) -> Self {
+        Self {
+            kind,
+            origin,
+            power: 1.0,
+            pierce,
+            hit: Vec::new(),
+            hit_germ: false,
+        }
+    }
+
+    pub(crate) fn radius(&self) -> f32 {
+        self.kind.radius() * self.power
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum BulletKind {
+    Normal,
+    /// Slow and big, hits hard.
+    Heavy,
+    /// Wide but weak shot that fades out after a short distance.
+    Pulse,
+}
+
+impl BulletKind {
+    pub(crate) fn radius(&self) -> f32 {
+        match self {
+            BulletKind::Normal => 4.0,
+            BulletKind::Heavy => 8.0,
+            BulletKind::Pulse => 16.0,
+        }
+    }
+
+    /// Speed relative to [`GameConfig::bullet_speed`].
+    pub(crate) fn speed_multiplier(&self) -> f32 {
+        match self {
+            BulletKind::Normal | BulletKind::Pulse => 1.0,
+            BulletKind::Heavy => 350.0 / 600.0,
+        }
+    }
+
+    /// Damage relative to a normal bullet.
+    pub(crate) fn damage_multiplier(&self) -> f32 {
+        match self {
+            BulletKind::Normal => 1.0,
+            BulletKind::Heavy => 2.5,
+            BulletKind::Pulse => 0.75,
+        }
+    }
+
+    /// Knockback relative to a normal bullet.
+    pub(crate) fn knockback_multiplier(&self) -> f32 {
+        match self {
+            BulletKind::Normal => 1.0,
+            BulletKind::Heavy => 2.0,
+            BulletKind::Pulse => 0.5,
+        }
+    }
+
+    /// Distance after which the bullet disappears. It doesn't count as a missed shot.
+    pub(crate) fn range(&self) -> Option<f32> {
+        match self {
+            BulletKind::Pulse => Some(200.0),
+            _ => None,
+        }
+    }
+}
+
+/// Pickup that clears a side effect, carried down by the bloodstream.
+#[derive(Component)]
+pub(crate) struct Cure;
+
+/// Pickup that restores patient health, carried down by the bloodstream.
+#[derive(Component)]
+pub(crate) struct HealthPickup {
+    pub(crate) amount: i32,
+}
+
+/// Measures the time from pressing fire to the resulting bullet spawn.
+#[derive(Resource, Default)]
+pub(crate) struct InputLatency {
+    /// Time of the fire press that hasn't spawned a bullet yet.
+    pub(crate) pressed_at: Option<Duration>,
+    /// Fixed update ticks since the press.
+    pub(crate) ticks: u32,
+    /// Latency and tick count of the last measured shot.
+    pub(crate) last: Option<(Duration, u32)>,
+}
+
+impl InputLatency {
+    pub(crate) fn bullet_spawned(&mut self, now: Duration) {
+        if let Some(pressed_at) = self.pressed_at.take() {
+            self.last = Some((now.saturating_sub(pressed_at), self.ticks));
+        }
+    }
+}
+
+pub(crate) fn player_movement(
+    input: Res<PlayerInput>,
+    boundaries: Res<Boundaries>,
+    config: Res<GameConfig>,
+    side_effects: Res<SideEffects>,
+    mut query: Query<(&mut Transform, &mut Physics, &mut Player)>,
+    mut tip_events: EventWriter<TipEvent>,
+) {
+    let (mut transform, mut physics, mut player) = query.single_mut();
+    let mut acceleration = Vec2::ZERO;
+
+    if input.left {
+        acceleration.x -= 1.0;
+    }
+    if input.right {
+        acceleration.x += 1.0;
+    }
+    if input.up {
+        acceleration.y += 1.0;
+    }
+    if input.down {
+        acceleration.y -= 1.0;
+    }
+    if side_effects.player_effect() == SideEffectType::ReversedControls {
+        acceleration = -acceleration;
+    }
+    acceleration = acceleration.normalize_or_zero();
+    acceleration.x *= 700.0;
+    acceleration.y *= 700.0;
+
+    physics.acceleration = acceleration;
+
+    let top_bound = boundaries.top - config.player_top_margin - physics.radius;
+    let bottom_bound = boundaries.bottom + physics.radius;
+
+    if transform.translation.y < bottom_bound {
+        transform.translation.y = bottom_bound;
+        physics.acceleration.y = physics.acceleration.y.max(0.0);
+        physics.velocity.y = physics.velocity.y.max(0.0);
+    } else if transform.translation.y > top_bound {
+        transform.translation.y = top_bound;
+        physics.acceleration.y = physics.acceleration.y.min(0.0);
+        physics.velocity.y = physics.velocity.y.min(0.0);
+    }
+
+    if transform.translation.y >= top_bound {
+        let was_camping = player.camping_time >= config.top_camping_time;
+        player.camping_time += TIME_STEP;
+        if config.top_camping_time > 0.0
+            && !was_camping
+            && player.camping_time >= config.top_camping_time
+        {
+            tip_events.send(TipEvent(Tip::TopCamping));
+        }
+    } else {
+        player.camping_time = 0.0;
+    }
+}
+
+/// Read the player input of this tick, from the replay being played back or the keyboard.
+pub(crate) fn read_player_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    settings: Res<Settings>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut input: ResMut<PlayerInput>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+) {
+    let recorder = &mut *recorder;
+    if let Some(playback) = &recorder.playback {
+        // The player stops once the replay runs out.
+        *input = playback
+            .inputs
+            .get(recorder.tick)
+            .copied()
+            .unwrap_or_default();
+        recorder.tick += 1;
+        return;
+    }
+    let pressed = |keys: &[KeyCode]| keyboard_input.any_pressed(keys.iter().copied());
+    let aim = match settings.shoot_mode {
+        ShootMode::Straight => None,
+        ShootMode::Aimed => window_query
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+            .and_then(|cursor| {
+                let (camera, camera_transform) = camera_query.get_single().ok()?;
+                camera.viewport_to_world(camera_transform, cursor)
+            })
+            .map(|ray| ray.origin.truncate()),
+    };
+    *input = PlayerInput {
+        left: pressed(&key_bindings.left),
+        right: pressed(&key_bindings.right),
+        up: pressed(&key_bindings.up),
+        down: pressed(&key_bindings.down),
+        shoot: pressed(&key_bindings.shoot),
+        secondary: pressed(&key_bindings.secondary),
+        aim,
+    };
+    recorder.recording.inputs.push(*input);
+}
+
+/// Start recording a new run. When playing back a replay, the run takes the replay's options
+/// instead of the ones picked on the welcome screen.
+fn start_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut run_options: ResMut<RunOptions>,
+    mut game_mode: ResMut<GameMode>,
+    mut run_modifiers: ResMut<RunModifiers>,
+) {
+    if let Some(playback) = &recorder.playback {
+        *game_mode = playback.mode;
+        run_modifiers.active = playback.modifiers.clone();
+        run_options.practice_score = playback.practice_score;
+        run_options.fixed_seed = Some(playback.seed);
+        run_options.daily = false;
+        run_options.retry = false;
+    }
+    recorder.tick = 0;
+    recorder.recording = Replay {
+        mode: *game_mode,
+        modifiers: run_modifiers.active.clone(),
+        practice_score: run_options.practice_score,
+        ..default()
+    };
+}
+
+/// Save the input of the finished run, along with the seed needed to play it back.
+fn save_replay(mut recorder: ResMut<ReplayRecorder>, run_options: Res<RunOptions>) {
+    if recorder.playback.is_some() {
+        return;
+    }
+    recorder.recording.seed = run_options.last_seed.unwrap_or_default();
+    save_ron(ReplayRecorder::FILE_NAME, &recorder.recording);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn player_shoot(
+    mut commands: Commands,
+    input: Res<PlayerInput>,
+    time: Res<Time>,
+    mut query: Query<(&Transform, &mut Physics, &mut Player)>,
+    spawner: Res<Spawner>,
+    side_effects: Res<SideEffects>,
+    settings: Res<Settings>,
+    config: Res<GameConfig>,
+    weapon: Res<WeaponState>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut latency: ResMut<InputLatency>,
+    mut pool: ResMut<BulletPool>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    if latency.pressed_at.is_some() {
+        latency.ticks += 1;
+    }
+    let (transform, mut physics, mut player) = query.single_mut();
+    let mut delta = Duration::from_secs_f32(TIME_STEP);
+    if config.top_camping_time > 0.0 && player.camping_time >= config.top_camping_time {
+        delta = delta.div_f32(config.top_camping_fire_slowdown.max(1.0));
+    }
+    let shoot_held = input.shoot;
+    let mut primary = player.shoot_timer.tick(delta).finished() && shoot_held;
+    let secondary = player.secondary_timer.tick(delta).finished() && input.secondary;
+    // The press itself still fires a normal shot, holding on charges instead of repeating it.
+    let mut charged = None;
+    if settings.charge_shot {
+        match (shoot_held, player.charge) {
+            (true, None) => player.charge = Some(0.0),
+            (true, Some(charge)) => {
+                player.charge = Some(charge + delta.as_secs_f32());
+                primary = false;
+            }
+            (false, Some(charge)) => {
+                player.charge = None;
+                if charge >= config.charge_min_time {
+                    charged = Some(charge);
+                }
+            }
+            (false, None) => {}
+        }
+    } else {
+        player.charge = None;
+    }
+    if !(primary || secondary || charged.is_some()) {
+        return;
+    }
+    if side_effects.player_effect() == SideEffectType::NoShooting || player.overheated {
+        return;
+    }
+    let position = transform.translation.truncate();
+    let direction = match settings.shoot_mode {
+        ShootMode::Straight => None,
+        ShootMode::Aimed => input.aim.and_then(|aim| (aim - position).try_normalize()),
+    }
+    .unwrap_or(Vec2::Y);
+    let mut fired = false;
+    if primary && player.try_spend_energy(config.shot_energy_cost) {
+        // The center bullet goes straight, the others fan out symmetrically around it.
+        let side = f32::from(weapon.spread / 2);
+        for i in 0..weapon.spread {
+            let angle = (f32::from(i) - side) * config.spread_angle.to_radians();
+            spawn_bullet(
+                &mut commands,
+                &mut pool,
+                &spawner,
+                &settings,
+                &config,
+                Vec2::from_angle(angle).rotate(direction),
+                PlayerBullet::new(BulletKind::Normal, position, weapon.pierce),
+            );
+        }
+        scoreboard.shots_fired += usize::from(weapon.spread);
+        player.add_heat(config.shot_heat);
+        player.shoot_timer.reset();
+        fired = true;
+        latency.bullet_spawned(time.raw_elapsed());
+    }
+    if let Some(charge) = charged {
+        if player.try_spend_energy(config.shot_energy_cost) {
+            let fraction = (charge / config.charge_full_time).min(1.0);
+            spawn_bullet(
+                &mut commands,
+                &mut pool,
+                &spawner,
+                &settings,
+                &config,
+                direction,
+                PlayerBullet {
+                    power: 1.0 + fraction * (config.charge_max_power - 1.0).max(0.0),
+                    pierce: weapon.pierce.saturating_add(u8::from(fraction >= 1.0)),
+                    ..PlayerBullet::new(BulletKind::Normal, position, weapon.pierce)
+                },
+            );
+            scoreboard.shots_fired += 1;
+            player.add_heat(config.shot_heat);
+            player.shoot_timer.reset();
+            fired = true;
+        }
+    }
+    if secondary && player.try_spend_energy(config.shot_energy_cost) {
+        let kind = settings.secondary_weapon.bullet_kind();
+        spawn_bullet(
+            &mut commands,
+            &mut pool,
+            &spawner,
+            &settings,
+            &config,
+            direction,
+            PlayerBullet::new(kind, position, weapon.pierce),
+        );
+        scoreboard.shots_fired += 1;
+        player.add_heat(config.shot_heat);
+        // The weapon can be changed between runs, so the cooldown isn't fixed.
+        player.secondary_timer =
+            Timer::from_seconds(settings.secondary_weapon.cooldown(), TimerMode::Once);
+        fired = true;
+    }
+    if fired {
+        sound_events.send(SoundEvent::Shoot);
+    }
+    // player_movement keeps the player above the bottom edge.
+    if fired && side_effects.player_effect() == SideEffectType::RecoilShots {
+        physics.velocity.y -= config.recoil_speed;
+    }
+}
+
+/// Hidden bullet entities kept for reuse, so that shooting doesn't spawn and despawn entities.
+#[derive(Resource, Default)]
+pub(crate) struct BulletPool {
+    pub(crate) free: Vec<Entity>,
+    /// Bullets spawned because the pool was empty. They join the pool once they're released.
+    pub(crate) spawned: usize,
+}
+
+impl BulletPool {
+    /// Components of a bullet waiting in the pool.
+    pub(crate) fn bundle(spawner: &Spawner) -> MaterialMesh2dBundle<ColorMaterial> {
+        MaterialMesh2dBundle {
+            mesh: spawner.circle_mesh.clone(),
+            material: spawner.nano_color.clone(),
+            visibility: Visibility::Hidden,
+            ..default()
+        }
+    }
+
+    pub(crate) fn acquire(&mut self, commands: &mut Commands, spawner: &Spawner) -> Entity {
+        self.free.pop().unwrap_or_else(|| {
+            self.spawned += 1;
+            commands.spawn(Self::bundle(spawner)).id()
+        })
+    }
+
+    /// Hide the bullet and return it to the pool. Releasing it again in the same step is ignored.
+    pub(crate) fn release(&mut self, commands: &mut Commands, entity: Entity) {
+        if self.free.contains(&entity) {
+            return;
+        }
+        commands
+            .entity(entity)
+            .insert(Visibility::Hidden)
+            .remove::<(Physics, PlayerBullet)>();
+        self.free.push(entity);
+    }
+}
+
+fn spawn_bullet(
+    commands: &mut Commands,
+    pool: &mut BulletPool,
+    spawner: &Spawner,
+    settings: &Settings,
+    config: &GameConfig,
+    direction: Vec2,
+    bullet: PlayerBullet,
+) {
+    let scale = bullet.radius() / BulletKind::Normal.radius();
+    let entity = pool.acquire(commands, spawner);
+    commands.entity(entity).insert((
+        Transform::from_translation(bullet.origin.extend(1.0))
+            .with_rotation(Quat::from_rotation_arc_2d(Vec2::Y, direction))
+            .with_scale(settings.bullet_style.render_scale() * scale),
+        Visibility::Visible,
+        Physics {
+            velocity: direction * config.bullet_speed * bullet.kind.speed_multiplier(),
+            acceleration: vec2(0.0, 0.0),
+            elasticity: 0.9,
+            radius: bullet.radius(),
+        },
+        bullet,
+    ));
+}
+
+/// Timestamp fire presses for the input latency diagnostic, as soon as the input is read.
+fn fire_press_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    time: Res<Time>,
+    debug_overlay: Res<DebugOverlay>,
+    mut latency: ResMut<InputLatency>,
+) {
+    let shoot = key_bindings.shoot.iter().copied();
+    if !debug_overlay.visible || !keyboard_input.any_pressed(shoot.clone()) {
+        // A press that didn't lead to a shot, e.g. in a no shooting zone, isn't measured.
+        latency.pressed_at = None;
+    } else if keyboard_input.any_just_pressed(shoot) {
+        latency.pressed_at = Some(time.raw_elapsed());
+        latency.ticks = 0;
+    }
+}
+
+fn player_zone_system(
+    config: Res<GameConfig>,
+    mut side_effects: ResMut<SideEffects>,
+    query: Query<&Transform, With<Player>>,
+) {
+    for transform in &query {
+        side_effects.update_player_zones(transform.translation.x, config.zone_exit_margin);
+    }
+}
+
+fn player_energy_system(config: Res<GameConfig>, mut query: Query<&mut Player>) {
+    for mut player in &mut query {
+        player.energy =
+            (player.energy + config.energy_regen_rate * TIME_STEP).min(config.max_energy);
+        player.heat = (player.heat - config.heat_cooling_rate * TIME_STEP).max(0.0);
+        if player.overheated && player.heat <= config.overheat_recovery {
+            player.overheated = false;
+        }
+    }
+}
+
+/// Record the player path and move the ghost along the best run's path.
+fn ghost_system(
+    mut ghosts: ResMut<Ghosts>,
+    game_mode: Res<GameMode>,
+    game_rng: Res<GameRng>,
+    run_modifiers: Res<RunModifiers>,
+    player_query: Query<&Transform, (With<Player>, Without<Ghost>)>,
+    mut ghost_query: Query<(&mut Transform, &mut Visibility), With<Ghost>>,
+) {
+    for transform in &player_query {
+        ghosts.current.push(transform.translation.truncate());
+    }
+    let Ok((mut transform, mut visibility)) = ghost_query.get_single_mut() else {
+        return;
+    };
+    let key = GhostKey::new(*game_mode, game_rng.seed, &run_modifiers);
+    let tick = ghosts.current.len().saturating_sub(1);
+    match ghosts
+        .best
+        .get(&key)
+        .and_then(|best| best.positions.get(tick))
+    {
+        Some(position) => {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+        // The best run ended earlier.
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
+/// Show skin changes on the player that is already spawned.
+#[allow(clippy::type_complexity)]
+fn apply_player_skin(
+    settings: Res<Settings>,
+    spawner: Res<Spawner>,
+    mut query: Query<(&mut Handle<Image>, &mut Transform), Or<(With<Player>, With<Ghost>)>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (mut texture, mut transform) in &mut query {
+        *texture = spawner.player_textures[&settings.player_skin].clone();
+        transform.scale = settings.player_skin.scale();
+    }
+}
+
+/// Slow down the game while the patient's health is critical.
+fn last_breath_system(
+    config: Res<GameConfig>,
+    scoreboard: Res<Scoreboard>,
+    mut time_scale: ResMut<TimeScale>,
+) {
+    let critical = scoreboard.patient_hp > 0 && scoreboard.patient_hp < config.last_breath_hp;
+    let last_breath = if critical {
+        config.last_breath_speed.clamp(0.1, 1.0)
+    } else {
+        1.0
+    };
+    if time_scale.last_breath != last_breath {
+        time_scale.last_breath = last_breath;
+    }
+}
+
+/// Collect the pickups touching the player and despawn the ones that left the screen.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn pickup_system(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    config: Res<GameConfig>,
+    text_styles: Res<TextStyles>,
+    mut side_effects: ResMut<SideEffects>,
+    mut scoreboard: ResMut<Scoreboard>,
+    pickup_query: Query<
+        (Entity, &Transform, &Physics, Option<&HealthPickup>),
+        Or<(With<Cure>, With<HealthPickup>)>,
+    >,
+    player_query: Query<
+        (&Transform, &Physics),
+        (With<Player>, Without<Cure>, Without<HealthPickup>),
+    >,
+    mut fx_query: Query<(&SideFx, &mut Fade)>,
+) {
+    let (player_transform, player_physics) = player_query.single();
+    let player_position = player_transform.translation.truncate();
+    for (entity, transform, physics, health_pickup) in &pickup_query {
+        let position = transform.translation.truncate();
+        if position.y < boundaries.bottom - physics.radius {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let total_radius = physics.radius + player_physics.radius;
+        if position.distance_squared(player_position) > total_radius * total_radius {
+            continue;
+        }
+        commands.entity(entity).despawn();
+        if let Some(health_pickup) = health_pickup {
+            scoreboard.patient_hp =
+                (scoreboard.patient_hp + health_pickup.amount).min(config.max_patient_hp);
+            spawn_floating_text(
+                &mut commands,
+                &text_styles,
+                player_position + vec2(0.0, 40.0),
+                format!("+{} HP", health_pickup.amount),
+                HEALTH_PICKUP_COLOR,
+                1.0,
+            );
+            continue;
+        }
+        let side = if side_effects.player_effect() != SideEffectType::None {
+            Some(if side_effects.player_in_right {
+                SideFx::Right
+            } else {
+                SideFx::Left
+            })
+        } else {
+            None
+        };
+        let text = if let Some(side) = side {
+            side_effects.clear(side);
+            for (fx, mut fade) in &mut fx_query {
+                if *fx == side {
+                    fade.fade_out();
+                }
+            }
+            "CURED".to_owned()
+        } else {
+            let risk = if player_position.x > 0.0 {
+                &mut side_effects.right_effect_risk
+            } else {
+                &mut side_effects.left_effect_risk
+            };
+            *risk = (*risk - config.cure_risk_reduction).max(0);
+            format!("-{}% RISK", config.cure_risk_reduction)
+        };
+        spawn_floating_text(
+            &mut commands,
+            &text_styles,
+            player_position + vec2(0.0, 40.0),
+            text,
+            CURE_COLOR,
+            1.0,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn player_bullet_despawner(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    query: Query<(Entity, &Transform, &PlayerBullet)>,
+    mut side_effects: ResMut<SideEffects>,
+    mut side_effect_events: EventWriter<SideEffectUpdateEvent>,
+    mut tip_events: EventWriter<TipEvent>,
+    config: Res<GameConfig>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut pool: ResMut<BulletPool>,
+    cell_query: Query<&Transform, (With<Cell>, With<Physics>)>,
+) {
+    for (entity, transform, bullet) in &query {
+        let out_of_range = bullet
+            .kind
+            .range()
+            .is_some_and(|range| transform.translation.truncate().distance(bullet.origin) > range);
+        // Allow some buffer space (cells can momentarily go outside screen)
+        let left_playfield = transform.translation.y < boundaries.bottom - 120.0
+            || transform.translation.y > boundaries.top + 120.0;
+        if (out_of_range || left_playfield) && bullet.hit.is_empty() {
+            scoreboard.shots_wasted += 1;
+        }
+        if out_of_range {
+            pool.release(&mut commands, entity);
+            continue;
+        }
+        // Aimed shots can leave from the bottom, which isn't a missed shot at the side effects.
+        if transform.translation.y < boundaries.bottom - 120.0 {
+            pool.release(&mut commands, entity);
+            continue;
+        }
+        if transform.translation.y > boundaries.top + 120.0 {
+            pool.release(&mut commands, entity);
+            // A bullet that pierced a cell has already hit.
+            if !bullet.hit.is_empty() {
+                continue;
+            }
+            let right = transform.translation.x > 0.0;
+            if config.missed_shot_risk == MissedShotRisk::CellsOnSide
+                && !cell_query
+                    .iter()
+                    .any(|cell_transform| (cell_transform.translation.x > 0.0) == right)
+            {
+                // There was nothing to hit on this side.
+                continue;
+            }
+            if right {
+                let risk = side_effects.right_effect_risk;
+                side_effect_events.send(SideEffectUpdateEvent::Right { risk });
+            } else {
+                let risk = side_effects.left_effect_risk;
+                side_effect_events.send(SideEffectUpdateEvent::Left { risk });
+            }
+            side_effects.add_risk(
+                transform.translation.x,
+                config.missed_shot_effect_risk,
+                config.max_effect_risk,
+            );
+            if side_effects.left_effect_risk >= 100 || side_effects.right_effect_risk >= 100 {
+                tip_events.send(TipEvent(Tip::FullRisk));
+            }
+        }
+    }
+}
+
+/// Grant the bullet upgrades as the score grows.
+fn weapon_upgrade_system(
+    mut commands: Commands,
+    scoreboard: Res<Scoreboard>,
+    config: Res<GameConfig>,
+    text_styles: Res<TextStyles>,
+    mut weapon: ResMut<WeaponState>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if config.pierce_score_interval == 0 {
+        return;
+    }
+    let pierce = (scoreboard.score / config.pierce_score_interval).min(u8::MAX as usize) as u8;
+    if pierce <= weapon.pierce {
+        return;
+    }
+    weapon.pierce = pierce;
+    if let Ok(transform) = player_query.get_single() {
+        spawn_floating_text(
+            &mut commands,
+            &text_styles,
+            transform.translation.truncate() + vec2(0.0, 40.0),
+            format!("PIERCE {}", pierce),
+            COMBO_TEXT_COLOR,
+            1.2,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::CellType;
+
+    #[test]
+    fn shots_spend_energy_until_it_runs_out() {
+        let config = GameConfig {
+            shot_energy_cost: 30.0,
+            max_energy: 100.0,
+            ..default()
+        };
+        let mut app = App::new();
+        app.insert_resource(PlayerInput {
+            shoot: true,
+            ..default()
+        })
+        .insert_resource(Time::default())
+        .insert_resource(Spawner::default())
+        .insert_resource(SideEffects::new(&config))
+        .insert_resource(Settings::default())
+        .insert_resource(config)
+        .insert_resource(InputLatency::default())
+        .insert_resource(WeaponState::new(&Settings::default()))
+        .insert_resource(Scoreboard::default())
+        .insert_resource(BulletPool::default())
+        .add_event::<SoundEvent>()
+        .add_system(player_shoot);
+        let player = app
+            .world
+            .spawn((
+                Transform::default(),
+                Physics {
+                    velocity: Vec2::ZERO,
+                    acceleration: Vec2::ZERO,
+                    elasticity: 0.0,
+                    radius: 15.0,
+                },
+                Player {
+                    shoot_timer: Timer::from_seconds(0.0, TimerMode::Once),
+                    energy: 100.0,
+                    ..Player::new(&Settings::default(), &GameConfig::default())
+                },
+            ))
+            .id();
+        let mut bullets = app.world.query_filtered::<(), With<PlayerBullet>>();
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(app.world.get::<Player>(player).unwrap().energy, 10.0);
+        assert_eq!(bullets.iter(&app.world).count(), 3);
+
+        app.update();
+        assert_eq!(bullets.iter(&app.world).count(), 3);
+    }
+
+    #[test]
+    fn missed_shots_only_add_risk_on_sides_with_cells() {
+        let config = GameConfig {
+            missed_shot_risk: MissedShotRisk::CellsOnSide,
+            ..default()
+        };
+        let mut app = App::new();
+        app.add_event::<SideEffectUpdateEvent>()
+            .add_event::<TipEvent>()
+            .insert_resource(Boundaries::default())
+            .insert_resource(SideEffects::new(&config))
+            .insert_resource(Scoreboard::default())
+            .insert_resource(config)
+            .insert_resource(BulletPool::default())
+            .add_system(player_bullet_despawner);
+        // A single cell on the left side.
+        app.world.spawn((
+            Transform::from_xyz(-100.0, 0.0, 0.0),
+            Physics::new(30.0),
+            Cell::new(CellType::Body { patient_hp: 1 }, 30.0),
+        ));
+        let above_top = Boundaries::default().top + 200.0;
+        for x in [-100.0, 100.0] {
+            app.world.spawn((
+                Transform::from_xyz(x, above_top, 0.0),
+                PlayerBullet::new(BulletKind::Normal, vec2(x, 0.0), 0),
+            ));
+        }
+        app.update();
+        let side_effects = app.world.resource::<SideEffects>();
+        let expected = app.world.resource::<GameConfig>().missed_shot_effect_risk;
+        assert_eq!(side_effects.left_effect_risk, expected);
+        assert_eq!(side_effects.right_effect_risk, 0);
+    }
+
+    #[test]
+    fn player_stays_below_the_top_margin() {
+        let config = GameConfig {
+            player_top_margin: 50.0,
+            ..default()
+        };
+        let boundaries = Boundaries::default();
+        let radius = 15.0;
+        let top_bound = boundaries.top - 50.0 - radius;
+        let mut app = App::new();
+        app.add_event::<TipEvent>()
+            .insert_resource(PlayerInput {
+                up: true,
+                ..default()
+            })
+            .insert_resource(SideEffects::new(&config))
+            .insert_resource(boundaries)
+            .insert_resource(config)
+            .add_systems((player_movement.after(physics_objects), physics_objects));
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                Physics {
+                    velocity: Vec2::ZERO,
+                    acceleration: Vec2::ZERO,
+                    elasticity: 0.0,
+                    radius,
+                },
+                Player::new(&Settings::default(), &GameConfig::default()),
+            ))
+            .id();
+        let mut highest = f32::MIN;
+        for _ in 0..500 {
+            app.update();
+            highest = highest.max(app.world.get::<Transform>(entity).unwrap().translation.y);
+        }
+        assert_eq!(highest, top_bound);
+    }
+
+    #[test]
+    fn critical_health_slows_time_until_it_recovers() {
+        let config = GameConfig {
+            last_breath_hp: 15,
+            last_breath_speed: 0.8,
+            ..default()
+        };
+        let mut app = App::new();
+        app.insert_resource(Scoreboard::default())
+            .insert_resource(TimeScale::default())
+            .insert_resource(config)
+            .add_system(last_breath_system);
+        let mut factor_at_hp = |patient_hp| {
+            app.world.resource_mut::<Scoreboard>().patient_hp = patient_hp;
+            app.update();
+            app.world.resource::<TimeScale>().factor()
+        };
+        assert_eq!(factor_at_hp(50), 1.0);
+        assert_eq!(factor_at_hp(10), 0.8);
+        assert_eq!(factor_at_hp(20), 1.0);
+    }
+}
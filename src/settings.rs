@@ -0,0 +1,524 @@
+//! Player options kept between sessions, and the entries of the settings menu.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{AudioOutputs, AudioSettings},
+    config::{load_ron, save_ron},
+    player::BulletKind,
+    ui::Tip,
+};
+
+/// Player preferences, persisted between sessions.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub(crate) audio: AudioSettings,
+    pub(crate) bullet_style: BulletStyle,
+    pub(crate) secondary_weapon: SecondaryWeapon,
+    pub(crate) shoot_mode: ShootMode,
+    /// Holding the fire key charges a bigger shot instead of firing continuously.
+    pub(crate) charge_shot: bool,
+    /// Number of bullets fired in a fan by the primary weapon: 1, 3 or 5.
+    pub(crate) spread: u8,
+    pub(crate) player_skin: PlayerSkin,
+    /// Use the extra horizontal space of wide screens for the playfield.
+    pub(crate) wide_playfield: bool,
+    /// Multiplier for the screen shake, between 0 (disabled) and 1.
+    pub(crate) shake_intensity: f32,
+    pub(crate) hp_display: HpDisplay,
+    pub(crate) thousands_separator: ThousandsSeparator,
+    /// Show the number of germs and blood cells on screen.
+    pub(crate) show_cell_count: bool,
+    /// Mark where fast-falling cells can be hit by a bullet fired right now.
+    pub(crate) show_intercept: bool,
+    /// Speed of the whole game, between 0.5 and 1.5. Runs at other speeds than 1 aren't
+    /// recorded.
+    pub(crate) game_speed: f32,
+    /// Shorten animations such as fades.
+    pub(crate) reduce_motion: bool,
+    /// Replay the best run with the same seed as a ghost.
+    pub(crate) show_ghost: bool,
+    /// Tips that were already shown once.
+    pub(crate) seen_tips: HashSet<Tip>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            audio: AudioSettings::default(),
+            bullet_style: BulletStyle::default(),
+            secondary_weapon: SecondaryWeapon::default(),
+            shoot_mode: ShootMode::default(),
+            charge_shot: false,
+            spread: 1,
+            player_skin: PlayerSkin::default(),
+            wide_playfield: false,
+            shake_intensity: 1.0,
+            hp_display: HpDisplay::default(),
+            thousands_separator: ThousandsSeparator::default(),
+            show_cell_count: true,
+            show_intercept: false,
+            game_speed: 1.0,
+            reduce_motion: false,
+            show_ghost: true,
+            seen_tips: HashSet::new(),
+        }
+    }
+}
+
+impl Settings {
+    pub(crate) const FILE_NAME: &str = "settings.ron";
+
+    pub(crate) fn load() -> Self {
+        load_ron(Self::FILE_NAME)
+    }
+
+    pub(crate) fn save(&self) {
+        save_ron(Self::FILE_NAME, self);
+    }
+}
+
+/// Format a fraction between 0 and 1 as a percentage.
+pub(crate) fn percent_string(fraction: f32) -> String {
+    format!("{:.0}%", fraction * 100.0)
+}
+
+/// Change a fraction between 0 and 1 in steps of 10%.
+fn step_fraction(fraction: &mut f32, step: i32) {
+    *fraction = ((*fraction * 10.0).round() + step as f32).clamp(0.0, 10.0) / 10.0;
+}
+
+/// Character grouping the thousands of large numbers in the HUD.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ThousandsSeparator {
+    #[default]
+    Comma,
+    Period,
+    Space,
+    None,
+}
+
+impl ThousandsSeparator {
+    pub(crate) fn next(&self) -> ThousandsSeparator {
+        match self {
+            ThousandsSeparator::Comma => ThousandsSeparator::Period,
+            ThousandsSeparator::Period => ThousandsSeparator::Space,
+            ThousandsSeparator::Space => ThousandsSeparator::None,
+            ThousandsSeparator::None => ThousandsSeparator::Comma,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            ThousandsSeparator::Comma => "1,000",
+            ThousandsSeparator::Period => "1.000",
+            ThousandsSeparator::Space => "1 000",
+            ThousandsSeparator::None => "1000",
+        }
+    }
+
+    /// Format an integer with its digits grouped by thousands.
+    pub(crate) fn format(&self, value: i64) -> String {
+        let separator = match self {
+            ThousandsSeparator::Comma => ',',
+            ThousandsSeparator::Period => '.',
+            ThousandsSeparator::Space => ' ',
+            ThousandsSeparator::None => return value.to_string(),
+        };
+        let digits = value.unsigned_abs().to_string();
+        let mut result = String::new();
+        if value < 0 {
+            result.push('-');
+        }
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                result.push(separator);
+            }
+            result.push(digit);
+        }
+        result
+    }
+}
+
+/// How the patient health is shown in the HUD.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum HpDisplay {
+    /// Percentage of the maximum health.
+    #[default]
+    Percent,
+    /// Raw health value.
+    Value,
+}
+
+impl HpDisplay {
+    pub(crate) fn next(&self) -> HpDisplay {
+        match self {
+            HpDisplay::Percent => HpDisplay::Value,
+            HpDisplay::Value => HpDisplay::Percent,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            HpDisplay::Percent => "Percent",
+            HpDisplay::Value => "Value",
+        }
+    }
+}
+
+/// Appearance of the player.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub(crate) enum PlayerSkin {
+    #[default]
+    Nanobot,
+    Germ,
+    BloodCell,
+}
+
+impl PlayerSkin {
+    pub(crate) const ALL: &[PlayerSkin] =
+        &[PlayerSkin::Nanobot, PlayerSkin::Germ, PlayerSkin::BloodCell];
+
+    pub(crate) fn next(&self) -> PlayerSkin {
+        match self {
+            PlayerSkin::Nanobot => PlayerSkin::Germ,
+            PlayerSkin::Germ => PlayerSkin::BloodCell,
+            PlayerSkin::BloodCell => PlayerSkin::Nanobot,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            PlayerSkin::Nanobot => "Nanobot",
+            PlayerSkin::Germ => "Undercover germ",
+            PlayerSkin::BloodCell => "Blood cell",
+        }
+    }
+
+    pub(crate) fn texture_path(&self) -> &'static str {
+        match self {
+            PlayerSkin::Nanobot => "graphics/player.png",
+            PlayerSkin::Germ => "graphics/germ.png",
+            PlayerSkin::BloodCell => "graphics/bloodcell.png",
+        }
+    }
+
+    /// Scale of the sprite, matching the texture to the player's radius.
+    pub(crate) fn scale(&self) -> Vec3 {
+        match self {
+            PlayerSkin::Nanobot => Vec3::ONE,
+            PlayerSkin::Germ | PlayerSkin::BloodCell => Vec3::new(1.0 / 3.0, 1.0 / 3.0, 1.0),
+        }
+    }
+}
+
+/// Direction of the player's shots.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ShootMode {
+    /// Straight up.
+    #[default]
+    Straight,
+    /// Towards the mouse cursor.
+    Aimed,
+}
+
+impl ShootMode {
+    pub(crate) fn next(&self) -> ShootMode {
+        match self {
+            ShootMode::Straight => ShootMode::Aimed,
+            ShootMode::Aimed => ShootMode::Straight,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            ShootMode::Straight => "Straight up",
+            ShootMode::Aimed => "Towards the cursor",
+        }
+    }
+}
+
+/// Weapon fired with S, chosen in the settings.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum SecondaryWeapon {
+    #[default]
+    Heavy,
+    Pulse,
+}
+
+impl SecondaryWeapon {
+    pub(crate) fn next(&self) -> SecondaryWeapon {
+        match self {
+            SecondaryWeapon::Heavy => SecondaryWeapon::Pulse,
+            SecondaryWeapon::Pulse => SecondaryWeapon::Heavy,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            SecondaryWeapon::Heavy => "Heavy shot",
+            SecondaryWeapon::Pulse => "Short-range pulse",
+        }
+    }
+
+    pub(crate) fn bullet_kind(&self) -> BulletKind {
+        match self {
+            SecondaryWeapon::Heavy => BulletKind::Heavy,
+            SecondaryWeapon::Pulse => BulletKind::Pulse,
+        }
+    }
+
+    /// Seconds between shots.
+    pub(crate) fn cooldown(&self) -> f32 {
+        match self {
+            SecondaryWeapon::Heavy => 1.2,
+            SecondaryWeapon::Pulse => 0.8,
+        }
+    }
+}
+
+/// How the player bullets are drawn. Collisions always use the bullet's physics radius.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum BulletStyle {
+    #[default]
+    Normal,
+    Small,
+    Streak,
+}
+
+impl BulletStyle {
+    pub(crate) fn next(&self) -> BulletStyle {
+        match self {
+            BulletStyle::Normal => BulletStyle::Small,
+            BulletStyle::Small => BulletStyle::Streak,
+            BulletStyle::Streak => BulletStyle::Normal,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            BulletStyle::Normal => "Normal",
+            BulletStyle::Small => "Small",
+            BulletStyle::Streak => "Thin streak",
+        }
+    }
+
+    /// Scale applied to the unit circle mesh of a bullet.
+    pub(crate) fn render_scale(&self) -> Vec3 {
+        match self {
+            BulletStyle::Normal => Vec3::new(8.0, 8.0, 8.0),
+            BulletStyle::Small => Vec3::new(4.0, 4.0, 4.0),
+            BulletStyle::Streak => Vec3::new(2.0, 14.0, 1.0),
+        }
+    }
+}
+
+/// Entries of the settings menu, in display order.
+#[derive(Clone, Copy)]
+pub(crate) enum SettingsEntry {
+    MusicVolume,
+    SfxVolume,
+    VolumeMeter,
+    Music,
+    AudioOutput,
+    ShakeIntensity,
+    HpDisplay,
+    ThousandsSeparator,
+    CellCount,
+    BulletStyle,
+    SecondaryWeapon,
+    ShootMode,
+    ChargeShot,
+    Spread,
+    InterceptIndicator,
+    PlayerSkin,
+    WidePlayfield,
+    GameSpeed,
+    ReduceMotion,
+    Ghost,
+    ResetTips,
+}
+
+impl SettingsEntry {
+    pub(crate) const ALL: &[SettingsEntry] = &[
+        SettingsEntry::MusicVolume,
+        SettingsEntry::SfxVolume,
+        SettingsEntry::VolumeMeter,
+        SettingsEntry::Music,
+        SettingsEntry::AudioOutput,
+        SettingsEntry::ShakeIntensity,
+        SettingsEntry::HpDisplay,
+        SettingsEntry::ThousandsSeparator,
+        SettingsEntry::CellCount,
+        SettingsEntry::BulletStyle,
+        SettingsEntry::SecondaryWeapon,
+        SettingsEntry::ShootMode,
+        SettingsEntry::ChargeShot,
+        SettingsEntry::Spread,
+        SettingsEntry::InterceptIndicator,
+        SettingsEntry::PlayerSkin,
+        SettingsEntry::WidePlayfield,
+        SettingsEntry::GameSpeed,
+        SettingsEntry::ReduceMotion,
+        SettingsEntry::Ghost,
+        SettingsEntry::ResetTips,
+    ];
+
+    pub(crate) fn label(&self) -> &str {
+        match self {
+            SettingsEntry::MusicVolume => "Music volume",
+            SettingsEntry::SfxVolume => "Sound effect volume",
+            SettingsEntry::VolumeMeter => "Volume meter",
+            SettingsEntry::Music => "Background music (N)",
+            // The audio plugin always opens the default output, see check_output_device.
+            SettingsEntry::AudioOutput => "Audio output (not yet applied)",
+            SettingsEntry::ShakeIntensity => "Screen shake",
+            SettingsEntry::HpDisplay => "Patient health display",
+            SettingsEntry::ThousandsSeparator => "Number format",
+            SettingsEntry::CellCount => "Cell count",
+            SettingsEntry::BulletStyle => "Bullet style",
+            SettingsEntry::SecondaryWeapon => "Secondary weapon",
+            SettingsEntry::ShootMode => "Aiming",
+            SettingsEntry::ChargeShot => "Hold fire to charge",
+            SettingsEntry::Spread => "Spread shot",
+            SettingsEntry::InterceptIndicator => "Intercept indicator",
+            SettingsEntry::PlayerSkin => "Player skin",
+            SettingsEntry::WidePlayfield => "Wide playfield",
+            SettingsEntry::GameSpeed => "Game speed",
+            SettingsEntry::ReduceMotion => "Reduce motion",
+            SettingsEntry::Ghost => "Best run ghost",
+            SettingsEntry::ResetTips => "Reset tips",
+        }
+    }
+
+    pub(crate) fn value(&self, settings: &Settings) -> String {
+        match self {
+            SettingsEntry::MusicVolume => percent_string(settings.audio.music_volume),
+            SettingsEntry::SfxVolume => percent_string(settings.audio.sfx_volume),
+            SettingsEntry::VolumeMeter => on_off(settings.audio.show_meter).to_owned(),
+            SettingsEntry::Music => on_off(!settings.audio.music_muted).to_owned(),
+            SettingsEntry::AudioOutput => settings
+                .audio
+                .output_device
+                .clone()
+                .unwrap_or_else(|| "System default".to_owned()),
+            SettingsEntry::ShakeIntensity => percent_string(settings.shake_intensity),
+            SettingsEntry::HpDisplay => settings.hp_display.name().to_owned(),
+            SettingsEntry::ThousandsSeparator => settings.thousands_separator.name().to_owned(),
+            SettingsEntry::CellCount => on_off(settings.show_cell_count).to_owned(),
+            SettingsEntry::BulletStyle => settings.bullet_style.name().to_owned(),
+            SettingsEntry::SecondaryWeapon => settings.secondary_weapon.name().to_owned(),
+            SettingsEntry::ShootMode => settings.shoot_mode.name().to_owned(),
+            SettingsEntry::ChargeShot => on_off(settings.charge_shot).to_owned(),
+            SettingsEntry::Spread => match settings.spread {
+                1 => "Off".to_owned(),
+                spread => format!("{} bullets", spread),
+            },
+            SettingsEntry::InterceptIndicator => on_off(settings.show_intercept).to_owned(),
+            SettingsEntry::PlayerSkin => settings.player_skin.name().to_owned(),
+            SettingsEntry::WidePlayfield => on_off(settings.wide_playfield).to_owned(),
+            SettingsEntry::GameSpeed => percent_string(settings.game_speed),
+            SettingsEntry::ReduceMotion => on_off(settings.reduce_motion).to_owned(),
+            SettingsEntry::Ghost => on_off(settings.show_ghost).to_owned(),
+            SettingsEntry::ResetTips => format!("{} seen", settings.seen_tips.len()),
+        }
+    }
+
+    /// Change the setting; `step` is -1 or 1 depending on the pressed key.
+    pub(crate) fn change(&self, settings: &mut Settings, outputs: &AudioOutputs, step: i32) {
+        match self {
+            SettingsEntry::MusicVolume => step_fraction(&mut settings.audio.music_volume, step),
+            SettingsEntry::SfxVolume => step_fraction(&mut settings.audio.sfx_volume, step),
+            SettingsEntry::VolumeMeter => settings.audio.show_meter = !settings.audio.show_meter,
+            SettingsEntry::Music => settings.audio.music_muted = !settings.audio.music_muted,
+            SettingsEntry::AudioOutput => {
+                // Cycle through the default output followed by the available devices.
+                let options: Vec<Option<String>> = std::iter::once(None)
+                    .chain(outputs.0.iter().cloned().map(Some))
+                    .collect();
+                let current = options
+                    .iter()
+                    .position(|option| *option == settings.audio.output_device)
+                    .unwrap_or(0);
+                let next = (current as i32 + step).rem_euclid(options.len() as i32);
+                settings.audio.output_device = options[next as usize].clone();
+            }
+            SettingsEntry::ShakeIntensity => step_fraction(&mut settings.shake_intensity, step),
+            SettingsEntry::HpDisplay => settings.hp_display = settings.hp_display.next(),
+            SettingsEntry::ThousandsSeparator => {
+                settings.thousands_separator = settings.thousands_separator.next()
+            }
+            SettingsEntry::CellCount => settings.show_cell_count = !settings.show_cell_count,
+            SettingsEntry::BulletStyle => settings.bullet_style = settings.bullet_style.next(),
+            SettingsEntry::SecondaryWeapon => {
+                settings.secondary_weapon = settings.secondary_weapon.next()
+            }
+            SettingsEntry::ShootMode => settings.shoot_mode = settings.shoot_mode.next(),
+            SettingsEntry::ChargeShot => settings.charge_shot = !settings.charge_shot,
+            SettingsEntry::Spread => {
+                settings.spread = match settings.spread {
+                    1 => 3,
+                    3 => 5,
+                    _ => 1,
+                }
+            }
+            SettingsEntry::InterceptIndicator => settings.show_intercept = !settings.show_intercept,
+            SettingsEntry::PlayerSkin => settings.player_skin = settings.player_skin.next(),
+            SettingsEntry::WidePlayfield => settings.wide_playfield = !settings.wide_playfield,
+            SettingsEntry::GameSpeed => {
+                settings.game_speed =
+                    ((settings.game_speed * 10.0).round() + step as f32).clamp(5.0, 15.0) / 10.0
+            }
+            SettingsEntry::ReduceMotion => settings.reduce_motion = !settings.reduce_motion,
+            SettingsEntry::Ghost => settings.show_ghost = !settings.show_ghost,
+            SettingsEntry::ResetTips => settings.seen_tips.clear(),
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+/// Keys controlling the player. Can be changed with a `keybindings.ron` file in the save
+/// directory, e.g. to play with WASD.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct KeyBindings {
+    pub(crate) up: Vec<KeyCode>,
+    pub(crate) down: Vec<KeyCode>,
+    pub(crate) left: Vec<KeyCode>,
+    pub(crate) right: Vec<KeyCode>,
+    pub(crate) shoot: Vec<KeyCode>,
+    /// Fires the secondary weapon.
+    pub(crate) secondary: Vec<KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            up: vec![KeyCode::Up],
+            down: vec![KeyCode::Down],
+            left: vec![KeyCode::Left],
+            right: vec![KeyCode::Right],
+            shoot: vec![KeyCode::A, KeyCode::Space],
+            secondary: vec![KeyCode::S],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub(crate) const FILE_NAME: &str = "keybindings.ron";
+
+    pub(crate) fn load() -> Self {
+        load_ron(Self::FILE_NAME)
+    }
+}
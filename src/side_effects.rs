@@ -0,0 +1,478 @@
+//! Side-effect zones triggered by missed shots.
+
+use std::time::Duration;
+
+use bevy::{math::vec2, prelude::*, text::Text2dBounds};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::GameConfig,
+    game::{Boundaries, Fade, GameRng, GameState},
+    physics::TIME_STEP,
+    settings::Settings,
+    ui::{TextStyles, Tip, TipEvent},
+};
+
+/// Side-effect zones and the risk of missed shots.
+pub(crate) struct SideEffectsPlugin;
+
+impl Plugin for SideEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SideEffectUpdateEvent>()
+            .add_system(
+                side_effect_system
+                    .run_if(in_state(GameState::Running))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(update_side_effect_text.run_if(in_state(GameState::Running)));
+    }
+}
+
+/// Which missed shots increase the side-effect risk.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum MissedShotRisk {
+    /// Every shot leaving the screen.
+    #[default]
+    Always,
+    /// Only shots on a side where there are cells, so that suppressive fire isn't punished.
+    CellsOnSide,
+}
+
+/// Component related to side effects
+#[derive(Component, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum SideFx {
+    Left,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum SideEffectType {
+    None,
+    SlowerMovement,
+    FasterMovement,
+    NoShooting,
+    NoKnockback,
+    ReversedControls,
+    RecoilShots,
+    Darkness,
+}
+
+impl SideEffectType {
+    /// All the side effects that can occur.
+    pub(crate) const ALL: &[SideEffectType] = &[
+        SideEffectType::SlowerMovement,
+        SideEffectType::FasterMovement,
+        SideEffectType::NoShooting,
+        SideEffectType::NoKnockback,
+        SideEffectType::ReversedControls,
+        SideEffectType::RecoilShots,
+        SideEffectType::Darkness,
+    ];
+
+    pub(crate) fn random(rng: &mut impl Rng) -> SideEffectType {
+        Self::ALL[rng.gen_range(0..Self::ALL.len())]
+    }
+
+    /// Seconds the effect lasts.
+    pub(crate) fn duration(&self, config: &GameConfig) -> f32 {
+        match self {
+            SideEffectType::None
+            | SideEffectType::SlowerMovement
+            | SideEffectType::FasterMovement
+            | SideEffectType::NoShooting
+            | SideEffectType::NoKnockback
+            | SideEffectType::ReversedControls
+            | SideEffectType::RecoilShots
+            | SideEffectType::Darkness => config.side_effect_duration,
+        }
+    }
+
+    pub(crate) fn movement_multiplier(&self) -> f32 {
+        match self {
+            SideEffectType::SlowerMovement => 0.5,
+            SideEffectType::FasterMovement => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            SideEffectType::None => "None",
+            SideEffectType::SlowerMovement => "Slower movement",
+            SideEffectType::FasterMovement => "Faster movement",
+            SideEffectType::NoShooting => "No shooting\nTouch to kill",
+            SideEffectType::NoKnockback => "No bullet knockback",
+            SideEffectType::ReversedControls => "Reversed controls",
+            SideEffectType::RecoilShots => "Recoil shots",
+            SideEffectType::Darkness => "Darkness",
+        }
+    }
+
+    pub(crate) fn description(&self) -> &str {
+        match self {
+            SideEffectType::None => "No side effect.",
+            SideEffectType::SlowerMovement => "Everything in the zone moves at half speed.",
+            SideEffectType::FasterMovement => "Everything in the zone moves at double speed.",
+            SideEffectType::NoShooting => {
+                "You can't shoot in the zone, but touching cells damages them."
+            }
+            SideEffectType::NoKnockback => "Bullets don't push the cells back in the zone.",
+            SideEffectType::ReversedControls => "The movement keys are swapped in the zone.",
+            SideEffectType::RecoilShots => "Every shot pushes you down in the zone.",
+            SideEffectType::Darkness => "The zone is covered in darkness.",
+        }
+    }
+}
+
+pub(crate) enum SideEffectUpdateEvent {
+    Left { risk: i32 },
+    Right { risk: i32 },
+}
+
+#[derive(Resource)]
+pub(crate) struct SideEffects {
+    pub(crate) left_effect_risk: i32,
+    pub(crate) right_effect_risk: i32,
+    pub(crate) left_effect: SideEffectType,
+    pub(crate) right_effect: SideEffectType,
+    pub(crate) left_effect_x: f32,
+    pub(crate) right_effect_x: f32,
+    pub(crate) left_timer: Timer,
+    pub(crate) right_timer: Timer,
+    /// Whether the player counts as inside each zone, see [`SideEffects::update_player_zones`].
+    pub(crate) player_in_left: bool,
+    pub(crate) player_in_right: bool,
+}
+
+impl SideEffects {
+    pub(crate) fn new(config: &GameConfig) -> Self {
+        let duration = config.side_effect_duration;
+        Self {
+            left_effect_risk: 0,
+            right_effect_risk: 0,
+            left_effect: SideEffectType::None,
+            right_effect: SideEffectType::None,
+            left_effect_x: -100.0,
+            right_effect_x: 100.0,
+            left_timer: Timer::from_seconds(duration, TimerMode::Repeating),
+            right_timer: Timer::from_seconds(duration, TimerMode::Repeating),
+            player_in_left: false,
+            player_in_right: false,
+        }
+    }
+
+    /// Place the zone boundaries relative to the walls. Both the drawn zones and the gameplay
+    /// checks use these boundaries.
+    pub(crate) fn fit_zones(&mut self, boundaries: &Boundaries, config: &GameConfig) {
+        self.left_effect_x = boundaries.left_wall * (1.0 - config.left_zone_width.clamp(0.0, 1.0));
+        self.right_effect_x =
+            boundaries.right_wall * (1.0 - config.right_zone_width.clamp(0.0, 1.0));
+    }
+
+    /// End the effect of a side before its timer runs out.
+    pub(crate) fn clear(&mut self, side: SideFx) {
+        let (effect, timer) = match side {
+            SideFx::Left => (&mut self.left_effect, &mut self.left_timer),
+            SideFx::Right => (&mut self.right_effect, &mut self.right_timer),
+        };
+        *effect = SideEffectType::None;
+        timer.reset();
+    }
+
+    /// Center and width of a zone, spanning from its boundary to the wall.
+    pub(crate) fn zone_span(&self, side: SideFx, boundaries: &Boundaries) -> (f32, f32) {
+        let (effect_x, wall_x) = match side {
+            SideFx::Left => (self.left_effect_x, boundaries.left_wall),
+            SideFx::Right => (self.right_effect_x, boundaries.right_wall),
+        };
+        ((effect_x + wall_x) / 2.0, (effect_x - wall_x).abs())
+    }
+
+    /// Track which zone the player is in. Once inside, the player has to move `margin` past the
+    /// boundary to leave, so that jittering on the boundary doesn't toggle the effect.
+    pub(crate) fn update_player_zones(&mut self, player_x: f32, margin: f32) {
+        self.player_in_left =
+            zone_hysteresis(self.player_in_left, self.left_effect_x - player_x, margin);
+        self.player_in_right =
+            zone_hysteresis(self.player_in_right, player_x - self.right_effect_x, margin);
+    }
+
+    /// Add risk to the side containing `x`, without exceeding `max_risk`.
+    pub(crate) fn add_risk(&mut self, x: f32, amount: i32, max_risk: i32) {
+        let risk = if x > 0.0 {
+            &mut self.right_effect_risk
+        } else {
+            &mut self.left_effect_risk
+        };
+        *risk = (*risk + amount).min(max_risk);
+    }
+
+    /// The side effect applied to the player.
+    pub(crate) fn player_effect(&self) -> SideEffectType {
+        if self.player_in_right {
+            self.right_effect
+        } else if self.player_in_left {
+            self.left_effect
+        } else {
+            SideEffectType::None
+        }
+    }
+}
+
+/// Whether something is inside a zone, given its depth into the zone (negative if outside) and
+/// whether it was inside before.
+fn zone_hysteresis(was_inside: bool, depth: f32, margin: f32) -> bool {
+    if was_inside {
+        depth > -margin
+    } else {
+        depth > 0.0
+    }
+}
+
+fn update_side_effect_text(
+    side_effects: Res<SideEffects>,
+    mut query: Query<(&mut Text, &SideFx, &Fade)>,
+) {
+    for (mut text, fx, fade) in &mut query {
+        if fade.fading_out {
+            // Keep showing the expired effect.
+            continue;
+        }
+        text.sections[0].value = match fx {
+            SideFx::Left => format!(
+                "{}\n{:.0} seconds",
+                side_effects.left_effect.name(),
+                side_effects.left_timer.remaining_secs().ceil()
+            ),
+            SideFx::Right => format!(
+                "{}\n{:.0} seconds",
+                side_effects.right_effect.name(),
+                side_effects.right_timer.remaining_secs().ceil()
+            ),
+        }
+    }
+}
+
+/// Roll for a new side effect after a missed shot reported `risk`. Below 100 it's a chance of
+/// `risk` percent, at 100 or more the effect is guaranteed. A triggered effect consumes 100 of
+/// the accumulated risk and the excess carries over to the next one.
+fn roll_side_effect(accumulated: &mut i32, risk: i32, rng: &mut impl Rng) -> bool {
+    // Always roll, so that the random sequence of a seed doesn't depend on the risk.
+    let roll = rng.gen_range(0..100);
+    if risk < 100 && roll >= risk {
+        return false;
+    }
+    *accumulated = (*accumulated - 100).max(0);
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
+fn side_effect_system(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    text_styles: Res<TextStyles>,
+    mut side_effects: ResMut<SideEffects>,
+    query: Query<(Entity, &SideFx)>,
+    mut side_effect_events: EventReader<SideEffectUpdateEvent>,
+    mut tip_events: EventWriter<TipEvent>,
+    mut game_rng: ResMut<GameRng>,
+    settings: Res<Settings>,
+    config: Res<GameConfig>,
+    mut fade_query: Query<&mut Fade>,
+) {
+    let spawn_side_effect = |commands: &mut Commands,
+                             fx_component: SideFx,
+                             (center_x, width): (f32, f32),
+                             effect: &SideEffectType| {
+        let translation = vec2(center_x, 0.0);
+        let size = vec2(width, 720.0);
+        // Darkness is drawn over the cells and bullets instead of tinting the background.
+        let (color, alpha, z) = if *effect == SideEffectType::Darkness {
+            (Color::rgba(0.0, 0.0, 0.0, 0.0), 0.85, 2.0)
+        } else {
+            (Color::rgba(0.125, 0.5, 0.5, 0.0), 0.125, 0.5)
+        };
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite { color, ..default() },
+                transform: Transform {
+                    translation: translation.extend(z),
+                    scale: size.extend(1.0),
+                    ..default()
+                },
+                ..default()
+            },
+            fx_component,
+            Fade::fade_in(alpha, &settings),
+        ));
+        commands.spawn((
+            Text2dBundle {
+                text: Text {
+                    sections: vec![TextSection::new(
+                        effect.name().to_owned(),
+                        TextStyle {
+                            color: *text_styles.in_game.color.clone().set_a(0.0),
+                            ..text_styles.in_game.clone()
+                        },
+                    )],
+                    alignment: TextAlignment::Center,
+                    ..Default::default()
+                },
+                text_2d_bounds: Text2dBounds { size },
+                transform: Transform {
+                    translation: translation.extend(z + 0.125),
+                    ..default()
+                },
+                ..default()
+            },
+            fx_component,
+            Fade::fade_in(text_styles.in_game.color.a(), &settings),
+        ));
+    };
+    // Determine the side effect risk from events
+    let mut left_risk: Option<i32> = None;
+    let mut right_risk: Option<i32> = None;
+    for event in side_effect_events.iter() {
+        match *event {
+            SideEffectUpdateEvent::Left { risk } => left_risk = Some(risk),
+            SideEffectUpdateEvent::Right { risk } => right_risk = Some(risk),
+        }
+    }
+    // Update existing side effects
+    let step = Duration::from_secs_f32(TIME_STEP);
+    if side_effects.left_effect != SideEffectType::None
+        && side_effects.left_timer.tick(step).just_finished()
+    {
+        side_effects.left_timer.reset();
+        side_effects.left_effect = SideEffectType::None;
+        for (entity, fx) in &query {
+            if *fx == SideFx::Left {
+                if let Ok(mut fade) = fade_query.get_mut(entity) {
+                    fade.fade_out();
+                }
+            }
+        }
+        if side_effects.left_effect_risk >= 100 {
+            left_risk = Some(100);
+        }
+    }
+    if side_effects.right_effect != SideEffectType::None
+        && side_effects.right_timer.tick(step).just_finished()
+    {
+        side_effects.right_timer.reset();
+        side_effects.right_effect = SideEffectType::None;
+        for (entity, fx) in &query {
+            if *fx == SideFx::Right {
+                if let Ok(mut fade) = fade_query.get_mut(entity) {
+                    fade.fade_out();
+                }
+            }
+        }
+        if side_effects.right_effect_risk >= 100 {
+            right_risk = Some(100);
+        }
+    }
+    // Create new side effects
+    let rng = &mut game_rng.rng;
+    if side_effects.left_effect == SideEffectType::None {
+        if let Some(risk) = left_risk {
+            if roll_side_effect(&mut side_effects.left_effect_risk, risk, rng) {
+                side_effects.left_effect = SideEffectType::random(rng);
+                side_effects.left_timer = Timer::from_seconds(
+                    side_effects.left_effect.duration(&config),
+                    TimerMode::Repeating,
+                );
+                tip_events.send(TipEvent(Tip::FirstSideEffect));
+                spawn_side_effect(
+                    &mut commands,
+                    SideFx::Left,
+                    side_effects.zone_span(SideFx::Left, &boundaries),
+                    &side_effects.left_effect,
+                );
+            }
+        }
+    }
+    if side_effects.right_effect == SideEffectType::None {
+        if let Some(risk) = right_risk {
+            if roll_side_effect(&mut side_effects.right_effect_risk, risk, rng) {
+                side_effects.right_effect = SideEffectType::random(rng);
+                side_effects.right_timer = Timer::from_seconds(
+                    side_effects.right_effect.duration(&config),
+                    TimerMode::Repeating,
+                );
+                tip_events.send(TipEvent(Tip::FirstSideEffect));
+                spawn_side_effect(
+                    &mut commands,
+                    SideFx::Right,
+                    side_effects.zone_span(SideFx::Right, &boundaries),
+                    &side_effects.right_effect,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_on_zone_boundary_doesnt_toggle_the_effect() {
+        let config = GameConfig::default();
+        let mut side_effects = SideEffects {
+            right_effect: SideEffectType::SlowerMovement,
+            ..SideEffects::new(&GameConfig::default())
+        };
+        let boundary = side_effects.right_effect_x;
+        side_effects.update_player_zones(boundary + 10.0, config.zone_exit_margin);
+        assert_eq!(side_effects.player_effect(), SideEffectType::SlowerMovement);
+        // Jitter by less than the margin on both sides of the boundary.
+        let jitter = config.zone_exit_margin * 0.5;
+        for frame in 0..20 {
+            let offset = if frame % 2 == 0 { -jitter } else { jitter };
+            side_effects.update_player_zones(boundary + offset, config.zone_exit_margin);
+            assert_eq!(side_effects.player_effect(), SideEffectType::SlowerMovement);
+        }
+        // Moving past the margin leaves the zone.
+        side_effects.update_player_zones(
+            boundary - config.zone_exit_margin * 2.0,
+            config.zone_exit_margin,
+        );
+        assert_eq!(side_effects.player_effect(), SideEffectType::None);
+    }
+
+    #[test]
+    fn risk_never_exceeds_the_cap() {
+        let config = GameConfig::default();
+        let mut side_effects = SideEffects::new(&config);
+        for _ in 0..100 {
+            side_effects.add_risk(100.0, 7, config.max_effect_risk);
+            side_effects.add_risk(-100.0, 7, config.max_effect_risk);
+            assert!(side_effects.right_effect_risk <= config.max_effect_risk);
+            assert!(side_effects.left_effect_risk <= config.max_effect_risk);
+        }
+        assert_eq!(side_effects.right_effect_risk, config.max_effect_risk);
+        assert_eq!(side_effects.left_effect_risk, config.max_effect_risk);
+    }
+
+    #[test]
+    fn zone_widths_place_the_gameplay_boundaries() {
+        let config = GameConfig {
+            left_zone_width: 0.25,
+            right_zone_width: 0.5,
+            ..default()
+        };
+        let boundaries = Boundaries::default();
+        let mut side_effects = SideEffects::new(&config);
+        side_effects.fit_zones(&boundaries, &config);
+
+        assert_eq!(side_effects.left_effect_x, boundaries.left_wall * 0.75);
+        side_effects.update_player_zones(side_effects.left_effect_x - 1.0, 0.0);
+        assert!(side_effects.player_in_left);
+
+        assert_eq!(side_effects.right_effect_x, boundaries.right_wall * 0.5);
+        side_effects.update_player_zones(side_effects.right_effect_x + 1.0, 0.0);
+        assert!(side_effects.player_in_right);
+    }
+}
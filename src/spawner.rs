@@ -0,0 +1,1607 @@
+//! Cell waves, bosses and hazards, and what happens when cells leave the screen.
+
+use std::{collections::HashMap, ops::Range, time::Duration};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    math::vec2,
+    prelude::*,
+    reflect::TypeUuid,
+    sprite::{Anchor, MaterialMesh2dBundle, Mesh2dHandle},
+    utils::BoxedFuture,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::SoundEvent,
+    components::{Cell, CellType, Physics},
+    config::GameConfig,
+    game::{
+        Boundaries, GameMode, GameRng, GameState, RunModifier, RunModifiers, RunOptions, Scoreboard,
+    },
+    physics::TIME_STEP,
+    player::{Cure, HealthPickup, Player},
+    settings::{PlayerSkin, Settings},
+    side_effects::SideEffects,
+    ui::{
+        spawn_chain_line, spawn_floating_text, BossHealthBar, BossHealthFill, HitFlash,
+        ScreenShake, TextStyles, Tip, TipEvent, BOSS_HEALTH_BAR_SIZE, COMBO_TEXT_COLOR,
+        COMBO_VISUAL_MAX, PENALTY_TEXT_COLOR, TEXT_COLOR,
+    },
+};
+
+pub(crate) const CURE_COLOR: Color = Color::rgb(0.4, 1.0, 0.6);
+
+pub(crate) const HEALTH_PICKUP_COLOR: Color = Color::rgb(1.0, 0.45, 0.55);
+
+const ARMORED_GERM_COLOR: Color = Color::rgb(0.55, 0.65, 0.9);
+
+const SPLITTER_GERM_COLOR: Color = Color::rgb(0.9, 0.8, 0.45);
+
+const SHOOTER_GERM_COLOR: Color = Color::rgb(0.9, 0.5, 0.9);
+
+pub(crate) const ENEMY_BULLET_COLOR: Color = Color::rgb(0.7, 1.0, 0.3);
+
+/// Splitter germs of this generation don't split anymore.
+const SPLITTER_MAX_GENERATION: u8 = 2;
+
+const SPLITTER_MIN_RADIUS: f32 = 20.0;
+
+/// Radius of a cell whose sprite is drawn at its original size.
+pub(crate) const CELL_TEXTURE_RADIUS: f32 = 45.0;
+
+/// Upper limit of the configurable cell gravity, so that cells can't gain enough speed to cross
+/// the playfield before they can be shot.
+pub(crate) const MAX_CELL_GRAVITY: f32 = 200.0;
+
+/// How quickly the drawn size of a cell follows its actual radius.
+const CELL_VISUAL_EASING: f32 = 12.0;
+
+/// Cells shrinking below this radius are destroyed.
+pub(crate) const CELL_KILL_RADIUS: f32 = 16.0;
+
+/// Waves of cells, hazards and bosses, from spawning to despawning.
+pub(crate) struct SpawnerPlugin;
+
+impl Plugin for SpawnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<WaveScript>()
+            .init_asset_loader::<WaveScriptLoader>()
+            .insert_resource(BossFight::default())
+            .insert_resource(Combo::default())
+            .add_systems(
+                (
+                    spawner_system.run_if(in_state(GameState::Running)),
+                    scroller_system.run_if(in_state(GameState::Running)),
+                    cell_despawner.run_if(in_state(GameState::Running)),
+                    shooter_germ_system.run_if(in_state(GameState::Running)),
+                    hazard_system.run_if(in_state(GameState::Running)),
+                    dying_cell_system.run_if(in_state(GameState::Running)),
+                    spawn_protection_system.run_if(in_state(GameState::Running)),
+                    telegraph_system.run_if(in_state(GameState::Running)),
+                    boss_system.run_if(in_state(GameState::Running)),
+                    combo_system.run_if(in_state(GameState::Running)),
+                )
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// How the formation of each wave is chosen.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) enum FormationOrder {
+    /// Cells are always scattered randomly.
+    ScatterOnly,
+    /// A random formation for each wave.
+    #[default]
+    Random,
+    /// Go through the formations in order.
+    Cycle,
+}
+
+/// Kind of cell to spawn, as written in the wave scripts.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub(crate) enum SpawnKind {
+    BloodCell,
+    Germ,
+    ArmoredGerm,
+    SplitterGerm,
+    ShooterGerm,
+}
+
+impl SpawnKind {
+    pub(crate) fn cell_type(&self, config: &GameConfig) -> CellType {
+        match self {
+            SpawnKind::BloodCell => CellType::Body {
+                patient_hp: config.body_kill_damage,
+            },
+            SpawnKind::Germ => CellType::Germ,
+            SpawnKind::ArmoredGerm => CellType::ArmoredGerm {
+                hits_remaining: config.armored_germ_hits,
+            },
+            SpawnKind::SplitterGerm => CellType::SplitterGerm,
+            SpawnKind::ShooterGerm => CellType::ShooterGerm {
+                shoot_timer: Timer::from_seconds(
+                    config.shooter_germ_interval,
+                    TimerMode::Repeating,
+                ),
+            },
+        }
+    }
+
+    pub(crate) fn texture(&self, spawner: &Spawner) -> Handle<Image> {
+        match self {
+            SpawnKind::BloodCell => spawner.blood_texture.clone(),
+            SpawnKind::Germ | SpawnKind::SplitterGerm => spawner.germ_texture.clone(),
+            SpawnKind::ArmoredGerm => spawner.armored_germ_texture.clone(),
+            SpawnKind::ShooterGerm => spawner.shooter_germ_texture.clone(),
+        }
+    }
+
+    pub(crate) fn tint(&self) -> Color {
+        match self {
+            SpawnKind::BloodCell | SpawnKind::Germ => Color::WHITE,
+            SpawnKind::ArmoredGerm => ARMORED_GERM_COLOR,
+            SpawnKind::SplitterGerm => SPLITTER_GERM_COLOR,
+            SpawnKind::ShooterGerm => SHOOTER_GERM_COLOR,
+        }
+    }
+}
+
+/// Group of cells in a scripted wave.
+#[derive(Debug, Deserialize)]
+pub(crate) struct WaveEntry {
+    pub(crate) cell_type: SpawnKind,
+    pub(crate) count: usize,
+    /// Range of the spawn positions, from the left wall at -1 to the right wall at 1.
+    pub(crate) x_range: (f32, f32),
+    pub(crate) velocity: Vec2,
+}
+
+/// Waves played at the start of each run before the procedural spawner takes over, loaded
+/// from `assets/waves.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "6f0c5f3e-8d1b-4a52-9a57-2f3c1d6e9b41"]
+pub(crate) struct WaveScript {
+    pub(crate) waves: Vec<Vec<WaveEntry>>,
+}
+
+#[derive(Default)]
+struct WaveScriptLoader;
+
+impl AssetLoader for WaveScriptLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let script: WaveScript = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(script));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Handle of the wave script. Until it's loaded, or if it's missing, all waves are procedural.
+#[derive(Resource)]
+pub(crate) struct Waves {
+    pub(crate) script: Handle<WaveScript>,
+}
+
+/// Arrangement of the cells of a wave.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Formation {
+    /// Random x positions, one cell after another.
+    Scatter,
+    /// Side by side in a horizontal line.
+    Line,
+    /// A V pointing down, the middle cell enters first.
+    V,
+    /// An arc bulging upwards, the outer cells enter first.
+    Arc,
+}
+
+impl Formation {
+    const ALL: &[Formation] = &[
+        Formation::Scatter,
+        Formation::Line,
+        Formation::V,
+        Formation::Arc,
+    ];
+
+    /// Horizontal distance between neighboring cells of a row, in cell diameters.
+    const SPACING: f32 = 1.25;
+
+    /// Spawn positions of `count` cells with the given radius, relative to the bottom center of
+    /// the formation. Rows that wouldn't fit in `max_width` are broken up and stacked. The
+    /// scatter formation is random, so it isn't handled here.
+    fn offsets(&self, count: usize, radius: f32, max_width: f32) -> Vec<Vec2> {
+        let spacing = radius * 2.0 * Self::SPACING;
+        let per_row = (((max_width - radius * 2.0) / spacing).floor() as usize + 1).max(1);
+        let mut offsets = Vec::with_capacity(count);
+        let mut row_y = 0.0;
+        let mut remaining = count;
+        while remaining > 0 {
+            let row_count = remaining.min(per_row);
+            let half_width = (row_count - 1) as f32 * spacing / 2.0;
+            let mut row_height: f32 = 0.0;
+            for i in 0..row_count {
+                let x = i as f32 * spacing - half_width;
+                let y = match self {
+                    Formation::Scatter | Formation::Line => 0.0,
+                    Formation::V => x.abs() * 0.75,
+                    Formation::Arc if half_width > 0.0 => {
+                        half_width * 0.75 * (1.0 - (x / half_width).powi(2))
+                    }
+                    Formation::Arc => 0.0,
+                };
+                row_height = row_height.max(y);
+                offsets.push(vec2(x, row_y + y));
+            }
+            row_y += row_height + spacing;
+            remaining -= row_count;
+        }
+        offsets
+    }
+}
+
+/// Patient hp change when a cell leaves the screen from the bottom. Healthy body cells recover
+/// hp in proportion to their remaining health, escaping germs always cost a fixed amount.
+fn bottom_exit_hp_change(cell: &Cell, is_boss: bool, config: &GameConfig) -> i32 {
+    match cell.cell_type {
+        CellType::Body { .. } => (config.body_exit_heal as f32 * cell.health()).ceil() as i32,
+        _ if is_boss => -config.boss_exit_damage,
+        CellType::Germ
+        | CellType::SplitterGerm
+        | CellType::ShooterGerm { .. }
+        | CellType::ArmoredGerm { .. } => -config.germ_exit_damage,
+    }
+}
+
+/// Cell waiting above the screen while its entry is telegraphed. It gets its physics, and
+/// starts moving and colliding, once the timer finishes.
+#[derive(Component)]
+pub(crate) struct Telegraph {
+    pub(crate) timer: Timer,
+    pub(crate) physics: Option<Physics>,
+}
+
+/// Flashes per second of a [`SpawnWarning`].
+const TELEGRAPH_FLASH_RATE: f32 = 8.0;
+
+/// Marker at the top edge showing where a telegraphed cell will enter.
+///
+/// The cell itself is spawned right away and parked above the screen with a [`Telegraph`],
+/// so the warning only needs to know when to disappear.
+#[derive(Component)]
+pub(crate) struct SpawnWarning {
+    pub(crate) timer: Timer,
+}
+
+/// Giant germ appearing at score milestones. It takes reduced damage.
+#[derive(Component)]
+pub(crate) struct Boss;
+
+/// Progress of the boss fights of a run.
+#[derive(Resource, Default)]
+pub(crate) struct BossFight {
+    /// Score at which the next boss appears.
+    pub(crate) next_score: usize,
+    pub(crate) phase: BossPhase,
+}
+
+#[derive(Default)]
+pub(crate) enum BossPhase {
+    #[default]
+    Inactive,
+    /// A warning is shown and the regular spawns are paused before the boss enters.
+    Warning(Timer),
+    /// The boss is alive. Regular spawns resume once it's gone.
+    Fight,
+}
+
+impl BossFight {
+    pub(crate) fn new(config: &GameConfig) -> Self {
+        Self {
+            next_score: config.boss_score_interval,
+            phase: BossPhase::Inactive,
+        }
+    }
+
+    pub(crate) fn spawns_paused(&self) -> bool {
+        !matches!(self.phase, BossPhase::Inactive)
+    }
+}
+
+/// Chain of germ kills in quick succession.
+#[derive(Resource, Default)]
+pub(crate) struct Combo {
+    pub(crate) count: usize,
+    /// Seconds since the last kill of the chain.
+    pub(crate) since_last_kill: f32,
+    pub(crate) last_kill: Option<Vec2>,
+}
+
+impl Combo {
+    /// Count a germ kill at `position` and return the position of the previous kill if the
+    /// chain continues.
+    pub(crate) fn add_kill(&mut self, position: Vec2, window: f32) -> Option<Vec2> {
+        let previous = self.last_kill.filter(|_| self.since_last_kill <= window);
+        self.count = if previous.is_some() {
+            self.count + 1
+        } else {
+            1
+        };
+        self.since_last_kill = 0.0;
+        self.last_kill = Some(position);
+        previous
+    }
+}
+
+/// Destroyed cell that shrinks away before it's despawned. It no longer collides.
+#[derive(Component)]
+pub(crate) struct DyingCell;
+
+/// Patch of infection left behind by a germ.
+#[derive(Component)]
+pub(crate) struct Hazard {
+    pub(crate) lifetime: Timer,
+}
+
+/// Bullet fired by a shooter germ. It costs patient health when it hits the player.
+#[derive(Component)]
+pub(crate) struct EnemyBullet;
+
+#[derive(Resource, Default)]
+pub(crate) struct Spawner {
+    pub(crate) timer: Timer,
+    /// Number of waves spawned in the current run.
+    pub(crate) waves: usize,
+    pub(crate) circle_mesh: Mesh2dHandle,
+    pub(crate) nano_color: Handle<ColorMaterial>,
+    pub(crate) cure_color: Handle<ColorMaterial>,
+    pub(crate) health_pickup_color: Handle<ColorMaterial>,
+    pub(crate) enemy_bullet_color: Handle<ColorMaterial>,
+    pub(crate) player_textures: HashMap<PlayerSkin, Handle<Image>>,
+    pub(crate) blood_texture: Handle<Image>,
+    pub(crate) germ_texture: Handle<Image>,
+    pub(crate) armored_germ_texture: Handle<Image>,
+    pub(crate) shooter_germ_texture: Handle<Image>,
+    pub(crate) vein_side_texture: Handle<Image>,
+    pub(crate) vein_bg_texture: Handle<Image>,
+}
+
+/// Background sprite scrolling with the bloodstream. Each layer is made of two tiles stacked on
+/// top of each other, which take turns wrapping around.
+#[derive(Component)]
+pub(crate) struct Scroller {
+    /// Scroll speed relative to the bloodstream. Distant layers scroll slower.
+    pub(crate) parallax_factor: f32,
+    /// Height of a tile of the layer.
+    pub(crate) height: f32,
+}
+
+/// Height of the vein wall and background textures.
+pub(crate) const VEIN_TILE_HEIGHT: f32 = 1440.0;
+
+/// Scrolling vein wall sprite, on the left (-1) or right (1) side.
+#[derive(Component)]
+pub(crate) struct VeinSide(pub(crate) f32);
+
+fn scroller_system(
+    config: Res<GameConfig>,
+    scoreboard: Res<Scoreboard>,
+    boundaries: Res<Boundaries>,
+    mut query: Query<(&mut Transform, &Scroller)>,
+) {
+    let scroll_speed = config.background_scroll_speed * config.bloodstream_speed(scoreboard.score);
+    for (mut transform, scroller) in &mut query {
+        transform.translation.y -= scroll_speed * scroller.parallax_factor * TIME_STEP;
+        // Once the tile is below the screen, move it on top of the other tile of its layer.
+        if transform.translation.y + scroller.height / 2.0 <= boundaries.bottom {
+            transform.translation.y += scroller.height * 2.0;
+        }
+    }
+}
+
+/// Count down the spawn protection of the cells on screen, which flicker while protected.
+pub(crate) fn spawn_protection_system(
+    boundaries: Res<Boundaries>,
+    settings: Res<Settings>,
+    mut query: Query<(&Transform, &Physics, &mut Cell, &mut Sprite)>,
+) {
+    for (transform, physics, mut cell, mut sprite) in &mut query {
+        if cell.spawn_protection <= 0.0 {
+            continue;
+        }
+        if transform.translation.y - physics.radius < boundaries.top {
+            cell.spawn_protection -= TIME_STEP;
+        }
+        let visible = cell.spawn_protection <= 0.0
+            || (!settings.reduce_motion && (cell.spawn_protection * 10.0) as i32 % 2 == 0);
+        sprite.color.set_a(if visible { 1.0 } else { 0.4 });
+    }
+}
+
+/// Spawn the two halves of a destroyed splitter germ, moving apart sideways.
+fn spawn_split_germs(
+    commands: &mut Commands,
+    spawner: &Spawner,
+    config: &GameConfig,
+    position: Vec2,
+    parent: &Cell,
+    parent_physics: &Physics,
+) {
+    // Cells below 16 collapse on their own, so the halves are kept above that.
+    let radius = (parent.full_radius / 2.0).max(SPLITTER_MIN_RADIUS);
+    for side in [-1.0, 1.0] {
+        let translation = (position + vec2(side * radius, 0.0)).extend(1.0);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SPLITTER_GERM_COLOR,
+                    ..default()
+                },
+                transform: Transform::from_translation(translation)
+                    .with_scale(Vec3::splat(radius / CELL_TEXTURE_RADIUS)),
+                texture: spawner.germ_texture.clone(),
+                ..default()
+            },
+            Physics {
+                velocity: vec2(side * config.splitter_speed, parent_physics.velocity.y),
+                acceleration: parent_physics.acceleration,
+                elasticity: 0.9,
+                radius,
+            },
+            Cell {
+                top_bound: translation.y + radius,
+                cell_type: CellType::SplitterGerm,
+                target_radius: radius,
+                full_radius: radius,
+                visual_scale: radius / CELL_TEXTURE_RADIUS,
+                spawn_protection: 0.0,
+                generation: parent.generation + 1,
+            },
+            HitFlash {
+                tint: SPLITTER_GERM_COLOR,
+                ..default()
+            },
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn cell_despawner(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &mut Physics,
+        &mut Cell,
+        Option<&Boss>,
+    )>,
+    mut scoreboard: ResMut<Scoreboard>,
+    game_mode: Res<GameMode>,
+    run_modifiers: Res<RunModifiers>,
+    config: Res<GameConfig>,
+    text_styles: Res<TextStyles>,
+    settings: Res<Settings>,
+    mut combo: ResMut<Combo>,
+    mut shake: ResMut<ScreenShake>,
+    spawner: Res<Spawner>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for (entity, mut transform, mut physics, mut cell, boss) in &mut query {
+        let scale_diff = cell.target_radius - physics.radius;
+        let scale_speed = TIME_STEP * 100.0;
+        if scale_diff.abs() > scale_speed {
+            physics.radius += scale_diff.signum() * scale_speed;
+        } else {
+            physics.radius = cell.target_radius;
+        }
+        let target_scale = physics.radius / CELL_TEXTURE_RADIUS;
+        cell.visual_scale +=
+            (target_scale - cell.visual_scale) * (1.0 - (-CELL_VISUAL_EASING * TIME_STEP).exp());
+        transform.scale.x = cell.visual_scale;
+        transform.scale.y = cell.visual_scale;
+        if physics.radius < CELL_KILL_RADIUS {
+            cell.target_radius = 0.0;
+        }
+        if physics.radius < 5.0 {
+            commands
+                .entity(entity)
+                .remove::<(Cell, Physics)>()
+                .insert(DyingCell)
+                .despawn_descendants();
+            // Cells destroyed by bullets and by touching the player end up here alike.
+            let position = transform.translation.truncate();
+            match cell.cell_type {
+                CellType::Body { patient_hp } => {
+                    scoreboard.patient_hp -= patient_hp;
+                    shake.add_trauma(0.4);
+                    sound_events.send(SoundEvent::CellLost);
+                    spawn_floating_text(
+                        &mut commands,
+                        &text_styles,
+                        position,
+                        format!("-{} HP", patient_hp),
+                        PENALTY_TEXT_COLOR,
+                        1.0,
+                    );
+                }
+                CellType::Germ
+                | CellType::SplitterGerm
+                | CellType::ShooterGerm { .. }
+                | CellType::ArmoredGerm { .. } => {
+                    let score = if boss.is_some() {
+                        config.boss_kill_score
+                    } else {
+                        config.germ_kill_score
+                    } * run_modifiers.score_multiplier();
+                    scoreboard.score += score;
+                    let previous_kill = combo.add_kill(position, config.combo_window);
+                    let text = if combo.count > 1 {
+                        format!("+{} x{}", score, combo.count)
+                    } else {
+                        format!("+{}", score)
+                    };
+                    // Popups of longer chains are bigger and brighter.
+                    let chain = (combo.count.min(COMBO_VISUAL_MAX) - 1) as f32
+                        / (COMBO_VISUAL_MAX - 1) as f32;
+                    spawn_floating_text(
+                        &mut commands,
+                        &text_styles,
+                        position,
+                        text,
+                        *(COMBO_TEXT_COLOR * chain + TEXT_COLOR * (1.0 - chain)).set_a(1.0),
+                        1.0 + chain,
+                    );
+                    if let Some(previous) = previous_kill.filter(|_| !settings.reduce_motion) {
+                        spawn_chain_line(&mut commands, previous, position, chain);
+                    }
+                    if config.germ_kill_heal > 0 && !run_modifiers.has(RunModifier::Transfusion) {
+                        scoreboard.patient_hp = (scoreboard.patient_hp + config.germ_kill_heal)
+                            .min(config.max_patient_hp);
+                    }
+                }
+            }
+            if matches!(cell.cell_type, CellType::SplitterGerm)
+                && cell.generation < SPLITTER_MAX_GENERATION
+            {
+                spawn_split_germs(&mut commands, &spawner, &config, position, &cell, &physics);
+            }
+        } else if transform.translation.y + physics.radius < boundaries.bottom {
+            commands.entity(entity).despawn_recursive();
+            if cell.cell_type.is_germ() && *game_mode == GameMode::Sterile {
+                scoreboard.infected = true;
+            }
+            let hp_change = bottom_exit_hp_change(&cell, boss.is_some(), &config);
+            // The cell is already gone, so the popup is shown just above the bottom edge.
+            let popup_position = vec2(transform.translation.x, boundaries.bottom + 30.0);
+            if hp_change < 0 {
+                scoreboard.patient_hp += hp_change;
+                shake.add_trauma(0.4);
+                sound_events.send(SoundEvent::CellLost);
+                spawn_floating_text(
+                    &mut commands,
+                    &text_styles,
+                    popup_position,
+                    format!("{} HP", hp_change),
+                    PENALTY_TEXT_COLOR,
+                    1.0,
+                );
+            } else if !run_modifiers.has(RunModifier::Transfusion) {
+                scoreboard.patient_hp =
+                    (scoreboard.patient_hp + hp_change).min(config.max_patient_hp);
+                if hp_change > 0 {
+                    spawn_floating_text(
+                        &mut commands,
+                        &text_styles,
+                        popup_position,
+                        format!("+{} HP", hp_change),
+                        TEXT_COLOR,
+                        0.8,
+                    );
+                }
+            }
+        } else {
+            // Don't let cells go outside the screen from the top
+            if transform.translation.y + physics.radius > cell.top_bound {
+                transform.translation.y = cell.top_bound - physics.radius;
+            }
+            cell.top_bound = boundaries
+                .top
+                .max(transform.translation.y + physics.radius - TIME_STEP * 100.0);
+        }
+    }
+}
+
+/// Spawn, fade and collide the infection hazards left behind by germs.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn hazard_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    spawner: Res<Spawner>,
+    mut game_rng: ResMut<GameRng>,
+    mut side_effects: ResMut<SideEffects>,
+    scoreboard: Res<Scoreboard>,
+    mut hazard_query: Query<(Entity, &mut Transform, &mut Sprite, &mut Hazard)>,
+    mut cell_query: Query<(&Transform, &Physics, &mut Cell), Without<Hazard>>,
+    player_query: Query<(&Transform, &Physics), (With<Player>, Without<Hazard>)>,
+) {
+    let hazard_chance = (config.hazard_spawn_rate * TIME_STEP as f64).clamp(0.0, 1.0);
+    for (transform, _, cell) in &cell_query {
+        if cell.cell_type.is_germ() && game_rng.rng.gen_bool(hazard_chance) {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(0.5, 1.0, 0.5, 0.6),
+                        custom_size: Some(Vec2::splat(config.hazard_radius * 2.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        transform.translation.truncate().extend(0.25),
+                    ),
+                    texture: spawner.germ_texture.clone(),
+                    ..default()
+                },
+                Hazard {
+                    lifetime: Timer::from_seconds(config.hazard_lifetime, TimerMode::Once),
+                },
+            ));
+        }
+    }
+
+    let (player_transform, player_physics) = player_query.single();
+    let scroll_speed = config.background_scroll_speed * config.bloodstream_speed(scoreboard.score);
+    for (entity, mut transform, mut sprite, mut hazard) in &mut hazard_query {
+        // Hazards are carried by the bloodstream.
+        transform.translation.y -= scroll_speed * TIME_STEP;
+        if hazard
+            .lifetime
+            .tick(Duration::from_secs_f32(TIME_STEP))
+            .finished()
+        {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        sprite.color.set_a(0.6 * hazard.lifetime.percent_left());
+
+        let touches = |other: &Transform, radius: f32| {
+            let total_radius = config.hazard_radius + radius;
+            transform
+                .translation
+                .truncate()
+                .distance_squared(other.translation.truncate())
+                <= total_radius * total_radius
+        };
+        if touches(player_transform, player_physics.radius) {
+            side_effects.add_risk(
+                player_transform.translation.x,
+                config.hazard_player_risk,
+                config.max_effect_risk,
+            );
+            commands.entity(entity).despawn();
+            continue;
+        }
+        for (cell_transform, cell_physics, mut cell) in &mut cell_query {
+            if matches!(cell.cell_type, CellType::Body { .. })
+                && touches(cell_transform, cell_physics.radius)
+            {
+                cell.target_radius -= config.hazard_cell_damage;
+                commands.entity(entity).despawn();
+                break;
+            }
+        }
+    }
+}
+
+/// Let the shooter germs on screen fire at the player.
+pub(crate) fn shooter_germ_system(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    config: Res<GameConfig>,
+    spawner: Res<Spawner>,
+    mut cell_query: Query<(&Transform, &mut Cell), With<Physics>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player_position = player_query.single().translation.truncate();
+    for (transform, mut cell) in &mut cell_query {
+        let CellType::ShooterGerm { shoot_timer } = &mut cell.cell_type else {
+            continue;
+        };
+        if transform.translation.y > boundaries.top
+            || !shoot_timer
+                .tick(Duration::from_secs_f32(TIME_STEP))
+                .just_finished()
+        {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let direction = (player_position - position)
+            .try_normalize()
+            .unwrap_or(Vec2::NEG_Y);
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: spawner.circle_mesh.clone(),
+                material: spawner.enemy_bullet_color.clone(),
+                transform: Transform::from_translation(position.extend(1.0))
+                    .with_scale(Vec3::splat(config.enemy_bullet_radius * 2.0)),
+                ..default()
+            },
+            Physics {
+                velocity: direction * config.enemy_bullet_speed,
+                acceleration: Vec2::ZERO,
+                elasticity: 0.9,
+                radius: config.enemy_bullet_radius,
+            },
+            EnemyBullet,
+        ));
+    }
+}
+
+/// Shrink the destroyed cells away.
+pub(crate) fn dying_cell_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform), With<DyingCell>>,
+) {
+    let shrink_speed = TIME_STEP * 100.0 / CELL_TEXTURE_RADIUS;
+    for (entity, mut transform) in &mut query {
+        let scale = transform.scale.x - shrink_speed;
+        if scale <= 0.0 {
+            commands.entity(entity).despawn();
+        } else {
+            transform.scale.x = scale;
+            transform.scale.y = scale;
+        }
+    }
+}
+
+/// Number of cells in a wave at the given score.
+/// Grows every 90 points from `2..5` at the start to `6..9` at 360 points.
+pub(crate) fn enemy_count_range(score: usize) -> Range<usize> {
+    let min_enemies = 2 + (score / 90).min(4);
+    let max_enemies = min_enemies + 3;
+    min_enemies..max_enemies
+}
+
+/// Maximum horizontal speed and base vertical velocity of the cells in a wave at the given score.
+/// Each cell additionally falls up to 100 units per second faster.
+///
+/// The horizontal speed grows from 80 until it's capped at 200 at 250 points. The base vertical
+/// velocity is -10 until 25 points and capped at -200 at 500 points.
+pub(crate) fn velocity_params(score: usize) -> (f32, f32) {
+    let x_vel_randomness = 75.0 + (score as f32 / 2.0).clamp(5.0, 125.0);
+    let y_vel_base = -(score as f32 / 2.5).clamp(10.0, 200.0);
+    (x_vel_randomness, y_vel_base)
+}
+
+/// Spawn a cell of the given kind. Its entry is telegraphed first if enabled.
+fn spawn_cell(
+    commands: &mut Commands,
+    spawner: &Spawner,
+    boundaries: &Boundaries,
+    config: &GameConfig,
+    kind: SpawnKind,
+    position: Vec2,
+    physics: Physics,
+) {
+    let radius = physics.radius;
+    let translation = position.extend(1.0);
+    let cell = Cell {
+        top_bound: translation.y + radius,
+        cell_type: kind.cell_type(config),
+        target_radius: radius,
+        full_radius: radius,
+        visual_scale: radius / CELL_TEXTURE_RADIUS,
+        spawn_protection: config.spawn_protection,
+        generation: 0,
+    };
+    let tint = kind.tint();
+    let harmful = matches!(cell.cell_type, CellType::Body { .. });
+    if config.spawn_telegraph > 0.0 {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+                    custom_size: Some(vec2(radius * 2.0, 6.0)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(translation.x, boundaries.top - 3.0, 2.0),
+                ..default()
+            },
+            SpawnWarning {
+                timer: Timer::from_seconds(config.spawn_telegraph, TimerMode::Once),
+            },
+        ));
+    }
+    let mut entity = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: tint,
+                ..default()
+            },
+            transform: Transform::from_translation(translation),
+            texture: kind.texture(spawner),
+            ..default()
+        },
+        cell,
+        HitFlash {
+            tint,
+            harmful,
+            ..default()
+        },
+    ));
+    if config.spawn_telegraph > 0.0 {
+        entity.insert(Telegraph {
+            timer: Timer::from_seconds(config.spawn_telegraph, TimerMode::Once),
+            physics: Some(physics),
+        });
+    } else {
+        entity.insert(physics);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawner_system(
+    mut commands: Commands,
+    boundaries: Res<Boundaries>,
+    mut spawner: ResMut<Spawner>,
+    cell_query: Query<&Cell>,
+    scoreboard: Res<Scoreboard>,
+    mut game_rng: ResMut<GameRng>,
+    run_modifiers: Res<RunModifiers>,
+    config: Res<GameConfig>,
+    boss_fight: Res<BossFight>,
+    waves: Res<Waves>,
+    wave_scripts: Res<Assets<WaveScript>>,
+    run_options: Res<RunOptions>,
+) {
+    if boss_fight.spawns_paused() {
+        return;
+    }
+    if !(cell_query.is_empty()
+        || spawner
+            .timer
+            .tick(Duration::from_secs_f32(TIME_STEP))
+            .just_finished())
+    {
+        return;
+    }
+    spawner.timer.reset();
+    let rng = &mut game_rng.rng;
+    let range_x = boundaries.right_wall - boundaries.left_wall;
+    let radius = if run_modifiers.has(RunModifier::Crowded) {
+        35.0
+    } else {
+        45.0
+    };
+    let stream_speed = config.bloodstream_speed(scoreboard.score);
+    // Practice runs start at a higher difficulty than the scripted waves.
+    let scripted_wave = wave_scripts
+        .get(&waves.script)
+        .filter(|_| run_options.practice_score == 0)
+        .and_then(|script| script.waves.get(spawner.waves));
+    if let Some(wave) = scripted_wave {
+        spawner.waves += 1;
+        let mut y = boundaries.top + radius;
+        for entry in wave {
+            for _ in 0..entry.count {
+                // The range is given between the walls, so that it fits any playfield width.
+                let (from, to) = entry.x_range;
+                let center_x = (boundaries.left_wall + boundaries.right_wall) / 2.0;
+                let half_range = (range_x / 2.0 - radius).max(0.0);
+                let x = center_x + rng.gen_range(from.min(to)..=from.max(to)) * half_range;
+                // Scripted cells fall with the bloodstream like the procedural ones.
+                let mut velocity = vec2(entry.velocity.x, entry.velocity.y * stream_speed);
+                if entry.cell_type != SpawnKind::BloodCell
+                    && run_modifiers.has(RunModifier::Adrenaline)
+                {
+                    velocity.y *= 1.5;
+                }
+                let physics = Physics {
+                    velocity,
+                    acceleration: vec2(0.0, -config.cell_gravity(rng) * stream_speed),
+                    elasticity: 0.9,
+                    radius,
+                };
+                let position = vec2(x, y);
+                spawn_cell(
+                    &mut commands,
+                    &spawner,
+                    &boundaries,
+                    &config,
+                    entry.cell_type,
+                    position,
+                    physics,
+                );
+                y += radius * 2.0 + config.spawn_gap_min.max(0.0);
+            }
+        }
+        return;
+    }
+    let mut count = rng.gen_range(enemy_count_range(scoreboard.score));
+    if run_modifiers.has(RunModifier::Crowded) {
+        count += 2;
+    }
+    let (x_vel_randomness, y_vel_base) = velocity_params(scoreboard.score);
+    let formation = match config.formation_order {
+        FormationOrder::ScatterOnly => Formation::Scatter,
+        FormationOrder::Random => Formation::ALL[rng.gen_range(0..Formation::ALL.len())],
+        FormationOrder::Cycle => Formation::ALL[spawner.waves % Formation::ALL.len()],
+    };
+    spawner.waves += 1;
+    let positions: Vec<Vec2> = if formation == Formation::Scatter {
+        let mut y = boundaries.top + radius;
+        (0..count)
+            .map(|_| {
+                let min_x = boundaries.left_wall + radius;
+                let range_x = range_x - radius * 2.0;
+                let position = vec2(rng.gen_range(0.0..range_x) + min_x, y);
+                // Cells are at least a diameter apart vertically, so they never spawn overlapping.
+                let gap_range = (config.spawn_gap_max - config.spawn_gap_min).max(0.0);
+                y += radius * 2.0 + config.spawn_gap_min.max(0.0) + rng.gen::<f32>() * gap_range;
+                position
+            })
+            .collect()
+    } else {
+        let offsets = formation.offsets(count, radius, range_x);
+        let min_offset = offsets.iter().map(|o| o.x).fold(0.0, f32::min);
+        let max_offset = offsets.iter().map(|o| o.x).fold(0.0, f32::max);
+        let min_x = boundaries.left_wall + radius - min_offset;
+        let max_x = (boundaries.right_wall - radius - max_offset).max(min_x);
+        let center = vec2(rng.gen_range(min_x..=max_x), boundaries.top + radius);
+        offsets.into_iter().map(|offset| center + offset).collect()
+    };
+    // The cells of a formation move together to keep its shape, so they share a single gravity
+    // instead of drawing one each. Different gravities would pull the formation apart within a
+    // few seconds. The gravity still varies from one formation to the next.
+    let shared_motion = (formation != Formation::Scatter).then(|| {
+        (
+            vec2(
+                rng.gen_range(-x_vel_randomness..x_vel_randomness),
+                (y_vel_base - rng.gen_range(0.0..100.0)) * stream_speed,
+            ),
+            config.cell_gravity(rng),
+        )
+    });
+    for position in positions {
+        let (mut velocity, gravity) = shared_motion.unwrap_or_else(|| {
+            (
+                vec2(
+                    rng.gen_range(-x_vel_randomness..x_vel_randomness),
+                    (y_vel_base - rng.gen_range(0.0..100.0)) * stream_speed,
+                ),
+                config.cell_gravity(rng),
+            )
+        });
+        let kind = if rng.gen_bool(0.5) {
+            SpawnKind::BloodCell
+        } else {
+            if run_modifiers.has(RunModifier::Adrenaline) {
+                velocity.y *= 1.5;
+            }
+            if rng.gen_bool(config.armored_germ_chance.clamp(0.0, 1.0)) {
+                SpawnKind::ArmoredGerm
+            } else if rng.gen_bool(config.splitter_germ_chance.clamp(0.0, 1.0)) {
+                SpawnKind::SplitterGerm
+            } else if rng.gen_bool(config.shooter_germ_chance.clamp(0.0, 1.0)) {
+                SpawnKind::ShooterGerm
+            } else {
+                SpawnKind::Germ
+            }
+        };
+        let physics = Physics {
+            velocity,
+            acceleration: vec2(0.0, -gravity * stream_speed),
+            elasticity: 0.9,
+            radius,
+        };
+        spawn_cell(
+            &mut commands,
+            &spawner,
+            &boundaries,
+            &config,
+            kind,
+            position,
+            physics,
+        );
+    }
+    let pickup_physics = |radius: f32| Physics {
+        velocity: vec2(0.0, y_vel_base * stream_speed),
+        acceleration: vec2(0.0, -config.cell_gravity_min * stream_speed),
+        elasticity: 0.9,
+        radius,
+    };
+    if rng.gen_bool(config.cure_chance.clamp(0.0, 1.0)) {
+        spawn_pickup(
+            &mut commands,
+            &spawner,
+            &boundaries,
+            rng,
+            spawner.cure_color.clone(),
+            pickup_physics(config.cure_radius),
+            Cure,
+        );
+    }
+    // Transfusion runs don't heal, so there's no point in health pickups.
+    if !run_modifiers.has(RunModifier::Transfusion)
+        && rng.gen_bool(config.health_pickup_chance.clamp(0.0, 1.0))
+    {
+        spawn_pickup(
+            &mut commands,
+            &spawner,
+            &boundaries,
+            rng,
+            spawner.health_pickup_color.clone(),
+            pickup_physics(config.health_pickup_radius),
+            HealthPickup {
+                amount: config.health_pickup_amount,
+            },
+        );
+    }
+}
+
+/// Spawn a pickup at a random position above the screen.
+fn spawn_pickup(
+    commands: &mut Commands,
+    spawner: &Spawner,
+    boundaries: &Boundaries,
+    rng: &mut impl Rng,
+    material: Handle<ColorMaterial>,
+    physics: Physics,
+    pickup: impl Component,
+) {
+    let radius = physics.radius;
+    let min_x = boundaries.left_wall + radius;
+    let max_x = (boundaries.right_wall - radius).max(min_x);
+    let x = rng.gen_range(min_x..=max_x);
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: spawner.circle_mesh.clone(),
+            material,
+            transform: Transform::from_xyz(x, boundaries.top + radius, 1.0)
+                .with_scale(Vec3::splat(radius * 2.0)),
+            ..default()
+        },
+        physics,
+        pickup,
+    ));
+}
+
+pub(crate) fn telegraph_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut cell_query: Query<(Entity, &mut Telegraph)>,
+    mut marker_query: Query<(Entity, &mut Sprite, &mut SpawnWarning)>,
+) {
+    let delta = Duration::from_secs_f32(TIME_STEP);
+    for (entity, mut telegraph) in &mut cell_query {
+        if telegraph.timer.tick(delta).finished() {
+            let mut entity = commands.entity(entity);
+            entity.remove::<Telegraph>();
+            if let Some(physics) = telegraph.physics.take() {
+                entity.insert(physics);
+            }
+        }
+    }
+    for (entity, mut sprite, mut marker) in &mut marker_query {
+        if marker.timer.tick(delta).finished() {
+            commands.entity(entity).despawn();
+        } else if !settings.reduce_motion {
+            // Flash to draw the eye to the entry point.
+            let phase = marker.timer.elapsed_secs() * TELEGRAPH_FLASH_RATE;
+            sprite
+                .color
+                .set_a(if phase.fract() < 0.5 { 0.8 } else { 0.3 });
+        }
+    }
+}
+
+/// Warn about the boss at score milestones, then send it in with a brief invulnerability.
+#[allow(clippy::too_many_arguments)]
+fn boss_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    spawner: Res<Spawner>,
+    boundaries: Res<Boundaries>,
+    scoreboard: Res<Scoreboard>,
+    mut boss_fight: ResMut<BossFight>,
+    boss_query: Query<(), (With<Boss>, With<Cell>)>,
+    mut tip_events: EventWriter<TipEvent>,
+) {
+    if config.boss_score_interval == 0 {
+        return;
+    }
+    match &mut boss_fight.phase {
+        BossPhase::Inactive => {
+            if scoreboard.score >= boss_fight.next_score {
+                boss_fight.phase = BossPhase::Warning(Timer::from_seconds(
+                    config.boss_warning_time,
+                    TimerMode::Once,
+                ));
+                tip_events.send(TipEvent(Tip::Boss));
+            }
+        }
+        BossPhase::Warning(timer) => {
+            if timer.tick(Duration::from_secs_f32(TIME_STEP)).finished() {
+                let radius = config.boss_radius;
+                let translation = Vec3::new(0.0, boundaries.top + radius, 1.0);
+                commands
+                    .spawn((
+                        SpriteBundle {
+                            transform: Transform::from_translation(translation),
+                            texture: spawner.germ_texture.clone(),
+                            ..default()
+                        },
+                        Physics {
+                            velocity: vec2(0.0, -40.0),
+                            acceleration: Vec2::ZERO,
+                            elasticity: 0.9,
+                            radius,
+                        },
+                        Cell {
+                            top_bound: translation.y + radius,
+                            cell_type: CellType::Germ,
+                            target_radius: radius,
+                            full_radius: radius,
+                            visual_scale: radius / CELL_TEXTURE_RADIUS,
+                            spawn_protection: config.boss_intro_protection,
+                            generation: 0,
+                        },
+                        Boss,
+                        HitFlash::default(),
+                    ))
+                    .with_children(|builder| {
+                        builder
+                            .spawn((
+                                SpriteBundle {
+                                    sprite: Sprite {
+                                        color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                                        custom_size: Some(BOSS_HEALTH_BAR_SIZE),
+                                        ..default()
+                                    },
+                                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                                    ..default()
+                                },
+                                BossHealthBar,
+                            ))
+                            .with_children(|builder| {
+                                builder.spawn((
+                                    SpriteBundle {
+                                        sprite: Sprite {
+                                            color: PENALTY_TEXT_COLOR,
+                                            custom_size: Some(BOSS_HEALTH_BAR_SIZE),
+                                            anchor: Anchor::CenterLeft,
+                                            ..default()
+                                        },
+                                        transform: Transform::from_xyz(
+                                            -BOSS_HEALTH_BAR_SIZE.x / 2.0,
+                                            0.0,
+                                            0.1,
+                                        ),
+                                        ..default()
+                                    },
+                                    BossHealthFill,
+                                ));
+                            });
+                    });
+                boss_fight.phase = BossPhase::Fight;
+            }
+        }
+        BossPhase::Fight => {
+            if boss_query.is_empty() {
+                boss_fight.phase = BossPhase::Inactive;
+                boss_fight.next_score = scoreboard.score + config.boss_score_interval;
+            }
+        }
+    }
+}
+
+fn combo_system(mut combo: ResMut<Combo>) {
+    combo.since_last_kill += TIME_STEP;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        physics::{physics_objects, player_collisions},
+        side_effects::SideEffectType,
+    };
+
+    /// App running [`cell_despawner`] on each update.
+    fn despawner_app(config: GameConfig) -> App {
+        let mut app = App::new();
+        app.insert_resource(Boundaries::default())
+            .insert_resource(Scoreboard::default())
+            .insert_resource(GameMode::default())
+            .insert_resource(RunModifiers::default())
+            .insert_resource(TextStyles {
+                in_game: default(),
+                label_style: default(),
+            })
+            .insert_resource(Settings::default())
+            .insert_resource(Combo::default())
+            .insert_resource(ScreenShake::default())
+            .insert_resource(Spawner::default())
+            .insert_resource(config)
+            .add_event::<SoundEvent>()
+            .add_system(cell_despawner);
+        app
+    }
+
+    /// App running [`spawner_system`] and [`scroller_system`] on each update, with a fixed seed.
+    /// Only the first update spawns a wave.
+    fn spawner_app(config: GameConfig) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<WaveScript>()
+            .insert_resource(Boundaries::default())
+            .insert_resource(Spawner {
+                timer: Timer::from_seconds(3600.0, TimerMode::Repeating),
+                ..default()
+            })
+            .insert_resource(Scoreboard::default())
+            .insert_resource(GameRng::new(7))
+            .insert_resource(RunModifiers::default())
+            .insert_resource(BossFight::new(&config))
+            .insert_resource(Waves {
+                script: Handle::default(),
+            })
+            .insert_resource(RunOptions::default())
+            .insert_resource(config)
+            .add_systems((spawner_system, scroller_system));
+        app
+    }
+
+    /// Spawn a cell that has shrunk enough to be destroyed on the next update.
+    fn spawn_dying_cell(app: &mut App, cell_type: CellType) -> Entity {
+        app.world
+            .spawn((
+                TransformBundle::default(),
+                Physics::new(1.0),
+                Cell {
+                    target_radius: 0.0,
+                    ..Cell::new(cell_type, 45.0)
+                },
+            ))
+            .id()
+    }
+
+    #[test]
+    fn germ_kills_heal_up_to_the_maximum() {
+        let mut app = despawner_app(GameConfig {
+            germ_kill_heal: 5,
+            max_patient_hp: 100,
+            ..default()
+        });
+        app.world.resource_mut::<Scoreboard>().patient_hp = 90;
+        spawn_dying_cell(&mut app, CellType::Germ);
+        app.update();
+        assert_eq!(app.world.resource::<Scoreboard>().patient_hp, 95);
+
+        spawn_dying_cell(&mut app, CellType::Germ);
+        app.update();
+        spawn_dying_cell(&mut app, CellType::Germ);
+        app.update();
+        assert_eq!(app.world.resource::<Scoreboard>().patient_hp, 100);
+    }
+
+    #[test]
+    fn bloodstream_speed_scales_scrolling_and_falling_alike() {
+        let spawn_wave = |bloodstream_speed| {
+            let mut app = spawner_app(GameConfig {
+                bloodstream_speed_base: bloodstream_speed,
+                bloodstream_speed_max: bloodstream_speed,
+                spawn_telegraph: 0.0,
+                ..default()
+            });
+            let scroller = app
+                .world
+                .spawn((
+                    TransformBundle::default(),
+                    Scroller {
+                        parallax_factor: 1.0,
+                        height: 10_000.0,
+                    },
+                ))
+                .id();
+            app.update();
+            let scrolled = -app.world.get::<Transform>(scroller).unwrap().translation.y;
+            let mut query = app.world.query::<&Physics>();
+            let motion: Vec<(f32, f32)> = query
+                .iter(&app.world)
+                .map(|physics| (physics.velocity.y, physics.acceleration.y))
+                .collect();
+            (scrolled, motion)
+        };
+        let (slow_scroll, slow_motion) = spawn_wave(1.0);
+        let (fast_scroll, fast_motion) = spawn_wave(2.0);
+        assert!(!slow_motion.is_empty());
+        assert!((fast_scroll / slow_scroll - 2.0).abs() < 1e-4);
+        for ((slow_velocity, slow_gravity), (fast_velocity, fast_gravity)) in
+            slow_motion.into_iter().zip(fast_motion)
+        {
+            assert!((fast_velocity / slow_velocity - 2.0).abs() < 1e-4);
+            assert!((fast_gravity / slow_gravity - 2.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn scripted_waves_follow_the_bloodstream_and_adrenaline() {
+        let mut app = spawner_app(GameConfig {
+            bloodstream_speed_base: 2.0,
+            bloodstream_speed_max: 2.0,
+            cell_gravity_min: 20.0,
+            cell_gravity_max: 20.0,
+            spawn_telegraph: 0.0,
+            ..default()
+        });
+        let entry = |cell_type| WaveEntry {
+            cell_type,
+            count: 1,
+            x_range: (0.0, 0.0),
+            velocity: vec2(10.0, -50.0),
+        };
+        let script = app
+            .world
+            .resource_mut::<Assets<WaveScript>>()
+            .add(WaveScript {
+                waves: vec![vec![entry(SpawnKind::BloodCell), entry(SpawnKind::Germ)]],
+            });
+        app.insert_resource(Waves { script })
+            .insert_resource(RunModifiers {
+                offered: Vec::new(),
+                active: vec![RunModifier::Adrenaline],
+            });
+        app.update();
+        let mut query = app.world.query::<(&Physics, &Cell)>();
+        let mut motion: Vec<(bool, Vec2, f32)> = query
+            .iter(&app.world)
+            .map(|(physics, cell)| {
+                let germ = matches!(cell.cell_type, CellType::Germ);
+                (germ, physics.velocity, physics.acceleration.y)
+            })
+            .collect();
+        motion.sort_by_key(|(germ, _, _)| *germ);
+        assert_eq!(
+            motion,
+            vec![
+                (false, vec2(10.0, -100.0), -40.0),
+                (true, vec2(10.0, -150.0), -40.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn telegraphed_cells_wait_for_the_delay() {
+        let config = GameConfig {
+            spawn_telegraph: 0.5,
+            ..default()
+        };
+        let mut app = spawner_app(config);
+        app.insert_resource(SideEffects::new(&GameConfig::default()))
+            .insert_resource(Settings::default())
+            .add_systems((telegraph_system, physics_objects));
+        app.update();
+        let mut cells = app
+            .world
+            .query_filtered::<(Entity, &Transform), With<Cell>>();
+        let spawned: Vec<(Entity, Vec3)> = cells
+            .iter(&app.world)
+            .map(|(entity, transform)| (entity, transform.translation))
+            .collect();
+        assert!(!spawned.is_empty());
+        // Just short of the delay.
+        for _ in 0..70 {
+            app.update();
+        }
+        for (entity, translation) in &spawned {
+            assert!(app.world.get::<Physics>(*entity).is_none());
+            assert_eq!(
+                app.world.get::<Transform>(*entity).unwrap().translation,
+                *translation
+            );
+        }
+        for _ in 0..10 {
+            app.update();
+        }
+        for (entity, translation) in &spawned {
+            assert!(app.world.get::<Physics>(*entity).is_some());
+            assert_ne!(
+                app.world.get::<Transform>(*entity).unwrap().translation,
+                *translation
+            );
+        }
+    }
+
+    #[test]
+    fn formation_offsets() {
+        // Cells of radius 20 are spaced 50 apart.
+        assert_eq!(
+            Formation::Line.offsets(3, 20.0, 640.0),
+            vec![vec2(-50.0, 0.0), vec2(0.0, 0.0), vec2(50.0, 0.0)]
+        );
+        assert_eq!(
+            Formation::V.offsets(3, 20.0, 640.0),
+            vec![vec2(-50.0, 37.5), vec2(0.0, 0.0), vec2(50.0, 37.5)]
+        );
+        assert_eq!(
+            Formation::Arc.offsets(3, 20.0, 640.0),
+            vec![vec2(-50.0, 0.0), vec2(0.0, 37.5), vec2(50.0, 0.0)]
+        );
+        // Only three cells fit in a row, the rest are stacked above.
+        assert_eq!(
+            Formation::Line.offsets(5, 20.0, 140.0),
+            vec![
+                vec2(-50.0, 0.0),
+                vec2(0.0, 0.0),
+                vec2(50.0, 0.0),
+                vec2(-25.0, 50.0),
+                vec2(25.0, 50.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_seed_spawns_the_same_wave() {
+        let spawn_wave = |seed| {
+            let mut app = spawner_app(GameConfig {
+                spawn_telegraph: 0.0,
+                ..default()
+            });
+            app.insert_resource(GameRng::new(seed));
+            app.update();
+            let mut query = app.world.query::<(&Transform, &Physics)>();
+            query
+                .iter(&app.world)
+                .map(|(transform, physics)| (transform.translation, physics.velocity))
+                .collect::<Vec<_>>()
+        };
+        let seed = RunOptions {
+            fixed_seed: Some(1234),
+            ..default()
+        }
+        .seed();
+        assert_eq!(seed, 1234);
+        assert_eq!(spawn_wave(seed), spawn_wave(seed));
+        assert_ne!(spawn_wave(seed), spawn_wave(seed + 1));
+    }
+
+    #[test]
+    fn spawned_cells_are_staggered_without_overlapping() {
+        let config = || GameConfig {
+            spawn_telegraph: 0.0,
+            formation_order: FormationOrder::ScatterOnly,
+            ..default()
+        };
+        let mut app = spawner_app(config());
+        app.world.resource_mut::<Scoreboard>().score = 400;
+        app.update();
+
+        let mut cells = app.world.query::<(&Transform, &Physics)>();
+        let mut positions: Vec<(Vec2, f32)> = cells
+            .iter(&app.world)
+            .map(|(transform, physics)| (transform.translation.truncate(), physics.radius))
+            .collect();
+        positions.sort_by(|a, b| a.0.y.total_cmp(&b.0.y));
+        let radius = positions[0].1;
+        let gaps: Vec<f32> = positions.windows(2).map(|w| w[1].0.y - w[0].0.y).collect();
+        assert!(gaps.len() >= 5);
+        for gap in &gaps {
+            assert!(*gap >= radius * 2.0 + config().spawn_gap_min);
+            assert!(*gap <= radius * 2.0 + config().spawn_gap_max);
+        }
+        assert!(gaps.iter().any(|gap| (gap - gaps[0]).abs() > 1.0));
+        for (i, (a, _)) in positions.iter().enumerate() {
+            for (b, _) in &positions[i + 1..] {
+                assert!(a.distance(*b) >= radius * 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn spawned_cells_fall_with_varied_gravity_within_the_bounds() {
+        let spawn_gravities = |cell_gravity_min, cell_gravity_max| {
+            let mut app = spawner_app(GameConfig {
+                cell_gravity_min,
+                cell_gravity_max,
+                spawn_telegraph: 0.0,
+                formation_order: FormationOrder::ScatterOnly,
+                ..default()
+            });
+            // Clear each wave, so that the next update spawns another one and there are enough
+            // cells to compare.
+            let mut gravities = Vec::new();
+            for _ in 0..4 {
+                app.update();
+                let mut cells = app.world.query_filtered::<(Entity, &Physics), With<Cell>>();
+                let spawned: Vec<(Entity, f32)> = cells
+                    .iter(&app.world)
+                    .map(|(entity, physics)| (entity, -physics.acceleration.y))
+                    .collect();
+                for (entity, gravity) in spawned {
+                    app.world.despawn(entity);
+                    gravities.push(gravity);
+                }
+            }
+            gravities
+        };
+        let config = GameConfig::default();
+        let gravities = spawn_gravities(config.cell_gravity_min, config.cell_gravity_max);
+        assert!(gravities.len() > 4);
+        for gravity in &gravities {
+            assert!((config.cell_gravity_min..=config.cell_gravity_max).contains(gravity));
+        }
+        assert!(gravities.iter().any(|gravity| *gravity != gravities[0]));
+
+        for gravity in spawn_gravities(150.0, 1000.0) {
+            assert!(gravity <= MAX_CELL_GRAVITY);
+        }
+    }
+
+    #[test]
+    fn difficulty_is_clamped_at_both_ends() {
+        assert_eq!(enemy_count_range(0), 2..5);
+        assert_eq!(enemy_count_range(180), 4..7);
+        assert_eq!(enemy_count_range(360), 6..9);
+        assert_eq!(enemy_count_range(100_000), 6..9);
+
+        assert_eq!(velocity_params(0), (80.0, -10.0));
+        assert_eq!(velocity_params(100), (125.0, -40.0));
+        assert_eq!(velocity_params(250), (200.0, -100.0));
+        assert_eq!(velocity_params(100_000), (200.0, -200.0));
+    }
+
+    #[test]
+    fn bottom_exit_hp_change_with_default_config() {
+        let config = GameConfig::default();
+        let body = Cell::new(CellType::Body { patient_hp: 1 }, 45.0);
+        assert_eq!(bottom_exit_hp_change(&body, false, &config), 1);
+        let germ = Cell::new(CellType::Germ, 45.0);
+        assert_eq!(bottom_exit_hp_change(&germ, false, &config), -10);
+    }
+
+    #[test]
+    fn touch_kills_score_germs_and_penalize_body_cells() {
+        let touch_kill = |cell_type| {
+            let mut side_effects = SideEffects {
+                right_effect: SideEffectType::NoShooting,
+                ..SideEffects::new(&GameConfig::default())
+            };
+            let player_x = side_effects.right_effect_x + 50.0;
+            side_effects.update_player_zones(player_x, 0.0);
+            let mut app = despawner_app(GameConfig::default());
+            app.insert_resource(side_effects)
+                .add_system(player_collisions.before(cell_despawner));
+            app.world.spawn((
+                Transform::from_xyz(player_x, 0.0, 0.0),
+                Physics::new(15.0),
+                Player::new(&Settings::default(), &GameConfig::default()),
+            ));
+            // A small cell overlapping the player, so that a single touch destroys it.
+            app.world.spawn((
+                Transform::from_xyz(player_x, 10.0, 0.0),
+                Physics::new(6.0),
+                Cell::new(cell_type, 6.0),
+            ));
+            for _ in 0..20 {
+                app.update();
+            }
+            let scoreboard = app.world.resource::<Scoreboard>();
+            (scoreboard.score, scoreboard.patient_hp)
+        };
+        let hp = Scoreboard::default().patient_hp;
+        assert_eq!(touch_kill(CellType::Germ), (1, hp));
+        assert_eq!(touch_kill(CellType::Body { patient_hp: 10 }), (0, hp - 10));
+    }
+}
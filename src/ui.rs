@@ -0,0 +1,1044 @@
+//! Menus, the HUD and the in-world popups.
+
+use bevy::{app::AppExit, math::vec2, prelude::*, window::PrimaryWindow};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{level_bar, stop_music, AudioOutputs, MusicResource},
+    components::{Cell, CellType, Physics},
+    config::GameConfig,
+    game::{
+        date_string, Boundaries, CountdownTimer, GameMode, GameRng, GameState, RunModifiers,
+        RunOptions, Scoreboard,
+    },
+    physics::{StepDebug, TimeScale},
+    player::{InputLatency, Player, NANO_COLOR},
+    settings::{HpDisplay, Settings, SettingsEntry},
+    side_effects::{SideEffectType, SideEffects},
+    spawner::{Boss, BossFight, BossPhase},
+};
+
+pub(crate) const TEXT_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
+
+pub(crate) const PENALTY_TEXT_COLOR: Color = Color::rgb(1.0, 0.35, 0.3);
+
+pub(crate) const COMBO_TEXT_COLOR: Color = Color::rgb(1.0, 0.9, 0.3);
+
+/// Combo count at which the popups stop growing.
+pub(crate) const COMBO_VISUAL_MAX: usize = 8;
+
+/// Menus, the scoreboard, popups and the debug overlays.
+pub(crate) struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TipEvent>()
+            .insert_resource(SettingsMenu::default())
+            .insert_resource(PauseMenu::default())
+            .insert_resource(TipBanner::default())
+            .insert_resource(ScreenShake::default())
+            .insert_resource(DebugOverlay::default())
+            .add_system(update_scoreboard.run_if(in_state(GameState::Running)))
+            .add_system(pause_system.run_if(in_state(GameState::Running)))
+            .add_system(pause_menu_system.run_if(in_state(GameState::Paused)))
+            .add_system(welcome_system.run_if(in_state(GameState::Init)))
+            .add_system(settings_menu_system.run_if(in_state(GameState::Init)))
+            .add_system(tip_system)
+            .add_system(legend_system)
+            .add_system(screen_shake_system)
+            .add_system(debug_overlay_system)
+            .add_system(cell_inspector_system)
+            .add_system(boss_banner_system)
+            .add_system(boss_healthbar_system)
+            .add_system(floating_text_system)
+            .add_system(hit_flash_system)
+            .add_system(last_breath_overlay_system)
+            .add_system(intercept_indicator_system.run_if(in_state(GameState::Running)))
+            .add_system(chain_line_system);
+    }
+}
+
+/// One-line hints shown the first time something happens.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub(crate) enum Tip {
+    FirstSideEffect,
+    FullRisk,
+    TopCamping,
+    Boss,
+}
+
+impl Tip {
+    pub(crate) fn text(&self) -> &str {
+        match self {
+            Tip::FirstSideEffect => {
+                "Missed shots caused a side effect! It applies inside the shaded zone."
+            }
+            Tip::FullRisk => {
+                "Risk is at 100%: the next missed shot on that side causes a side effect."
+            }
+            Tip::TopCamping => "Staying at the top edge slows down your shooting.",
+            Tip::Boss => "A boss can't be hurt while it enters and shrugs off most of the damage.",
+        }
+    }
+}
+
+/// Request to show a tip, ignored if the player has already seen it.
+pub(crate) struct TipEvent(pub(crate) Tip);
+
+/// Timed banner displaying the current tip.
+#[derive(Resource)]
+struct TipBanner {
+    timer: Timer,
+}
+
+impl Default for TipBanner {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(5.0, TimerMode::Once);
+        timer.tick(timer.duration());
+        Self { timer }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct TipText;
+
+#[derive(Resource, Default)]
+struct SettingsMenu {
+    open: bool,
+    selected: usize,
+}
+
+#[derive(Component)]
+pub(crate) struct SettingsText;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PauseEntry {
+    Resume,
+    Restart,
+    QuitToMenu,
+    QuitToDesktop,
+}
+
+impl PauseEntry {
+    const ALL: &[PauseEntry] = &[
+        PauseEntry::Resume,
+        PauseEntry::Restart,
+        PauseEntry::QuitToMenu,
+        PauseEntry::QuitToDesktop,
+    ];
+
+    fn label(&self) -> &str {
+        match self {
+            PauseEntry::Resume => "Resume",
+            PauseEntry::Restart => "Restart",
+            PauseEntry::QuitToMenu => "Quit to menu",
+            PauseEntry::QuitToDesktop => "Quit to desktop",
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct PauseMenu {
+    pub(crate) selected: usize,
+    /// Returning to a paused run, which must not be set up again.
+    pub(crate) resuming: bool,
+}
+
+/// Camera shake driven by trauma, which is added when the patient is hurt and decays over time.
+#[derive(Resource, Default)]
+pub(crate) struct ScreenShake {
+    pub(crate) trauma: f32,
+}
+
+impl ScreenShake {
+    /// Maximum camera offset in pixels.
+    pub(crate) const MAX_OFFSET: f32 = 16.0;
+    /// Trauma lost per second.
+    pub(crate) const DECAY: f32 = 1.5;
+
+    pub(crate) fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+#[derive(Component, PartialEq, Eq)]
+pub(crate) enum TopText {
+    Header,
+    Sub,
+    /// Restart prompt on the game over screen.
+    Prompt,
+}
+
+#[derive(Component)]
+pub(crate) struct WelcomeText;
+
+/// Welcome screen text that describes the selected game mode.
+#[derive(Component)]
+pub(crate) struct ModeText;
+
+/// Welcome screen text listing the offered run modifiers.
+#[derive(Component)]
+pub(crate) struct ModifiersText;
+
+/// Health bar floating above the boss, as its child.
+#[derive(Component)]
+pub(crate) struct BossHealthBar;
+
+/// Filled part of the [`BossHealthBar`], as its child.
+#[derive(Component)]
+pub(crate) struct BossHealthFill;
+
+pub(crate) const BOSS_HEALTH_BAR_SIZE: Vec2 = Vec2::new(120.0, 8.0);
+
+#[derive(Component)]
+pub(crate) struct BossBanner;
+
+/// Short-lived text rising from where something happened, e.g. the score of a kill.
+///
+/// Kill scores, hp changes and pickup messages all use it, so score popups don't have a
+/// component of their own.
+#[derive(Component)]
+pub(crate) struct FloatingText {
+    pub(crate) timer: Timer,
+}
+
+pub(crate) fn spawn_floating_text(
+    commands: &mut Commands,
+    text_styles: &TextStyles,
+    position: Vec2,
+    value: String,
+    color: Color,
+    scale: f32,
+) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                value,
+                TextStyle {
+                    font_size: 28.0 * scale,
+                    color,
+                    ..text_styles.in_game.clone()
+                },
+            )
+            .with_alignment(TextAlignment::Center),
+            transform: Transform::from_translation(position.extend(5.0)),
+            ..default()
+        },
+        FloatingText {
+            timer: Timer::from_seconds(0.8, TimerMode::Once),
+        },
+    ));
+}
+
+/// Line connecting two consecutive kills of a combo.
+#[derive(Component)]
+pub(crate) struct ChainLine {
+    pub(crate) timer: Timer,
+    pub(crate) alpha: f32,
+}
+
+pub(crate) fn spawn_chain_line(commands: &mut Commands, from: Vec2, to: Vec2, chain: f32) {
+    let diff = to - from;
+    let alpha = 0.4 + 0.5 * chain;
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: *COMBO_TEXT_COLOR.clone().set_a(alpha),
+                custom_size: Some(vec2(diff.length(), 2.0 + 4.0 * chain)),
+                ..default()
+            },
+            transform: Transform::from_translation(((from + to) / 2.0).extend(4.0))
+                .with_rotation(Quat::from_rotation_z(diff.y.atan2(diff.x))),
+            ..default()
+        },
+        ChainLine {
+            timer: Timer::from_seconds(0.5, TimerMode::Once),
+            alpha,
+        },
+    ));
+}
+
+/// Predicted point where a cell meets a bullet fired now.
+#[derive(Component)]
+pub(crate) struct InterceptMarker;
+
+/// Where a cell moving with constant `target_velocity` will be when a bullet fired straight up
+/// from `shooter` reaches its height. None if the bullet can't catch up with the cell.
+fn intercept_point(
+    shooter: Vec2,
+    bullet_speed: f32,
+    target: Vec2,
+    target_velocity: Vec2,
+) -> Option<Vec2> {
+    let closing_speed = bullet_speed - target_velocity.y;
+    if closing_speed <= 0.0 {
+        return None;
+    }
+    let time = (target.y - shooter.y) / closing_speed;
+    (time >= 0.0).then(|| target + target_velocity * time)
+}
+
+/// Brightens a cell for a moment after it's hit.
+#[derive(Component, Default)]
+pub(crate) struct HitFlash {
+    pub(crate) timer: Timer,
+    /// Color of the sprite outside of the flash.
+    pub(crate) tint: Color,
+    /// Flash red instead, for the body cells that shouldn't be shot.
+    pub(crate) harmful: bool,
+}
+
+impl HitFlash {
+    pub(crate) const DURATION: f32 = 0.08;
+
+    /// Start the flash, or restart it if the cell is hit again.
+    pub(crate) fn trigger(&mut self) {
+        self.timer = Timer::from_seconds(Self::DURATION, TimerMode::Once);
+    }
+}
+
+/// Developer diagnostics, toggled with F3.
+#[derive(Resource, Default)]
+pub(crate) struct DebugOverlay {
+    pub(crate) visible: bool,
+}
+
+#[derive(Component)]
+pub(crate) struct DebugText;
+
+/// Red overlay pulsing during the last breath slow motion.
+#[derive(Component)]
+pub(crate) struct LastBreathOverlay;
+
+/// Tooltip describing the cell under the cursor, shown with the debug overlay.
+#[derive(Component)]
+pub(crate) struct CellTooltip;
+
+/// Overlay explaining every side effect, toggled with L.
+#[derive(Component)]
+pub(crate) struct LegendText;
+
+pub(crate) fn legend_text() -> String {
+    let mut text = "SIDE EFFECTS".to_owned();
+    for effect in SideEffectType::ALL {
+        let name = effect.name().lines().next().unwrap_or_default();
+        text += &format!("\n{}: {}", name, effect.description());
+    }
+    text
+}
+
+#[derive(Resource)]
+pub(crate) struct TextStyles {
+    pub(crate) in_game: TextStyle,
+    pub(crate) label_style: TextStyle,
+}
+
+#[derive(Component)]
+pub(crate) enum ScoreboardText {
+    Score,
+    PatientHp,
+    LeftEffectRisk,
+    RightEffectRisk,
+    PatientHpLabel,
+    TimeLeft,
+    Seed,
+    Energy,
+    Heat,
+    GermCount,
+}
+
+fn screen_shake_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut shake: ResMut<ScreenShake>,
+    mut query: Query<&mut Transform, With<Camera2d>>,
+) {
+    shake.trauma = (shake.trauma - ScreenShake::DECAY * time.delta_seconds()).max(0.0);
+    // Squaring the trauma makes small amounts barely noticeable.
+    let magnitude =
+        ScreenShake::MAX_OFFSET * shake.trauma * shake.trauma * settings.shake_intensity;
+    // Shaking is purely visual, so it doesn't draw from the run's seeded RNG.
+    let mut rng = rand::thread_rng();
+    for mut transform in &mut query {
+        transform.translation.x = rng.gen_range(-1.0..=1.0) * magnitude;
+        transform.translation.y = rng.gen_range(-1.0..=1.0) * magnitude;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_scoreboard(
+    scoreboard: Res<Scoreboard>,
+    side_effects: Res<SideEffects>,
+    run_options: Res<RunOptions>,
+    game_rng: Res<GameRng>,
+    config: Res<GameConfig>,
+    settings: Res<Settings>,
+    game_mode: Res<GameMode>,
+    countdown: Res<CountdownTimer>,
+    player_query: Query<&Player>,
+    cell_query: Query<&Cell>,
+    mut query: Query<(&mut Text, &ScoreboardText)>,
+) {
+    let player = player_query.single();
+    let separator = settings.thousands_separator;
+    for (mut text, text_type) in &mut query {
+        if let ScoreboardText::TimeLeft = text_type {
+            let warning = countdown.timer.remaining_secs() <= config.time_attack_warning;
+            text.sections[0].style.color = if warning {
+                PENALTY_TEXT_COLOR
+            } else {
+                TEXT_COLOR
+            };
+        }
+        text.sections[0].value = match text_type {
+            ScoreboardText::Score => separator.format(scoreboard.score as i64),
+            ScoreboardText::PatientHpLabel => match settings.hp_display {
+                HpDisplay::Percent => "Patient Health".to_owned(),
+                HpDisplay::Value => format!(
+                    "Patient Health / {}",
+                    separator.format(config.max_patient_hp as i64)
+                ),
+            },
+            ScoreboardText::PatientHp => match settings.hp_display {
+                HpDisplay::Percent => format!(
+                    "{}%",
+                    scoreboard.patient_hp * 100 / config.max_patient_hp.max(1)
+                ),
+                HpDisplay::Value => separator.format(scoreboard.patient_hp as i64),
+            },
+            ScoreboardText::LeftEffectRisk => format!("{}%", side_effects.left_effect_risk),
+            ScoreboardText::RightEffectRisk => format!("{}%", side_effects.right_effect_risk),
+            ScoreboardText::TimeLeft if *game_mode == GameMode::TimeAttack => {
+                let seconds = countdown.timer.remaining_secs().ceil() as u32;
+                format!("Time left {}:{:02}", seconds / 60, seconds % 60)
+            }
+            ScoreboardText::TimeLeft => String::new(),
+            ScoreboardText::Seed if run_options.daily => {
+                format!("Daily {}", date_string(game_rng.seed))
+            }
+            ScoreboardText::Seed => format!("Seed {}", game_rng.seed),
+            ScoreboardText::Energy if config.shot_energy_cost > 0.0 => {
+                format!("Energy {}", level_bar(player.energy / config.max_energy))
+            }
+            ScoreboardText::Energy => String::new(),
+            ScoreboardText::Heat if player.overheated => "Heat OVERHEATED".to_owned(),
+            ScoreboardText::Heat if config.shot_heat > 0.0 => {
+                format!("Heat {}", level_bar(player.heat))
+            }
+            ScoreboardText::Heat => String::new(),
+            ScoreboardText::GermCount if settings.show_cell_count => {
+                let germs = cell_query
+                    .iter()
+                    .filter(|cell| cell.cell_type.is_germ())
+                    .count();
+                format!(
+                    "Germs {}\nBlood cells {}",
+                    germs,
+                    cell_query.iter().len() - germs
+                )
+            }
+            ScoreboardText::GermCount => String::new(),
+        }
+    }
+}
+
+/// Pause the run with ESC or the start button.
+fn pause_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let start_pressed = gamepads.iter().any(|gamepad| {
+        gamepad_input.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start))
+    });
+    if keyboard_input.just_pressed(KeyCode::Escape) || start_pressed {
+        pause_menu.selected = 0;
+        next_state.set(GameState::Paused);
+    }
+}
+
+/// Navigate the pause menu with the arrow keys or the d-pad and pick an entry with ENTER or
+/// the south button. ESC and the start button resume the run.
+#[allow(clippy::too_many_arguments)]
+fn pause_menu_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut run_options: ResMut<RunOptions>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut music_res: ResMut<MusicResource>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut query: Query<(&mut Text, &TopText)>,
+) {
+    let gamepad_pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+    let count = PauseEntry::ALL.len();
+    if keyboard_input.just_pressed(KeyCode::Up) || gamepad_pressed(GamepadButtonType::DPadUp) {
+        pause_menu.selected = (pause_menu.selected + count - 1) % count;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) || gamepad_pressed(GamepadButtonType::DPadDown) {
+        pause_menu.selected = (pause_menu.selected + 1) % count;
+    }
+    let chosen = if keyboard_input.just_pressed(KeyCode::Escape)
+        || gamepad_pressed(GamepadButtonType::Start)
+    {
+        Some(PauseEntry::Resume)
+    } else if keyboard_input.just_pressed(KeyCode::Return)
+        || gamepad_pressed(GamepadButtonType::South)
+    {
+        Some(PauseEntry::ALL[pause_menu.selected])
+    } else {
+        None
+    };
+
+    for (mut text, top_text) in &mut query {
+        text.sections[0].value = match (chosen, top_text) {
+            // Leaving the menu, the HUD of a running game has no top text.
+            (Some(_), _) => String::new(),
+            (None, TopText::Header) => "PAUSED".to_owned(),
+            (None, TopText::Sub) => {
+                let mut value = String::new();
+                for (i, entry) in PauseEntry::ALL.iter().enumerate() {
+                    let cursor = if i == pause_menu.selected { "> " } else { "" };
+                    value += &format!("{}{}\n", cursor, entry.label());
+                }
+                value + "Up/Down: Select, ENTER: Confirm"
+            }
+            (None, TopText::Prompt) => "PRESS ESC TO RESUME".to_owned(),
+        };
+    }
+
+    match chosen {
+        Some(PauseEntry::Resume) => {
+            pause_menu.resuming = true;
+            next_state.set(GameState::Running);
+        }
+        Some(PauseEntry::Restart) => {
+            run_options.retry = true;
+            next_state.set(GameState::Init);
+        }
+        Some(PauseEntry::QuitToMenu) => {
+            // The welcome screen has no music.
+            stop_music(&mut music_res, &audio_sinks);
+            next_state.set(GameState::Init);
+        }
+        Some(PauseEntry::QuitToDesktop) => {
+            // High scores are saved on game over, so quitting mid-run has nothing to record.
+            app_exit_events.send(AppExit);
+        }
+        None => {}
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn welcome_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut run_options: ResMut<RunOptions>,
+    mut run_modifiers: ResMut<RunModifiers>,
+    mut mode_text_query: Query<&mut Text, (With<ModeText>, Without<ModifiersText>)>,
+    mut modifiers_text_query: Query<&mut Text, With<ModifiersText>>,
+    settings_menu: Res<SettingsMenu>,
+    debug_overlay: Res<DebugOverlay>,
+) {
+    if settings_menu.open {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        next_state.set(GameState::Running);
+    }
+    if cfg!(debug_assertions) && debug_overlay.visible && keyboard_input.just_pressed(KeyCode::F7) {
+        next_state.set(GameState::CollisionLab);
+    }
+    if keyboard_input.just_pressed(KeyCode::M) {
+        *game_mode = game_mode.next();
+    }
+    if keyboard_input.just_pressed(KeyCode::D) {
+        run_options.daily = !run_options.daily;
+    }
+    if keyboard_input.just_pressed(KeyCode::P) {
+        run_options.next_practice_score();
+    }
+    for (i, key) in [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3]
+        .into_iter()
+        .enumerate()
+    {
+        if keyboard_input.just_pressed(key) {
+            run_modifiers.toggle(i);
+        }
+    }
+    if game_mode.is_changed() || run_options.is_changed() {
+        for mut text in &mut mode_text_query {
+            text.sections[0].value = run_options.banner(*game_mode);
+        }
+    }
+    if run_modifiers.is_changed() {
+        for mut text in &mut modifiers_text_query {
+            text.sections[0].value = run_modifiers.banner();
+        }
+    }
+}
+
+/// Open/close the settings menu with TAB and navigate it with the arrow keys.
+fn settings_menu_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings_menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    outputs: Res<AudioOutputs>,
+    mut query: Query<(&mut Text, &mut Visibility), With<SettingsText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        settings_menu.open = !settings_menu.open;
+    }
+    if settings_menu.open {
+        let count = SettingsEntry::ALL.len();
+        if keyboard_input.just_pressed(KeyCode::Up) {
+            settings_menu.selected = (settings_menu.selected + count - 1) % count;
+        }
+        if keyboard_input.just_pressed(KeyCode::Down) {
+            settings_menu.selected = (settings_menu.selected + 1) % count;
+        }
+        let step = if keyboard_input.just_pressed(KeyCode::Left) {
+            -1
+        } else if keyboard_input.just_pressed(KeyCode::Right) {
+            1
+        } else {
+            0
+        };
+        if step != 0 {
+            SettingsEntry::ALL[settings_menu.selected].change(&mut settings, &outputs, step);
+            settings.save();
+        }
+    }
+
+    for (mut text, mut visibility) in &mut query {
+        *visibility = if settings_menu.open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if settings_menu.open {
+            let mut value = "SETTINGS\n".to_owned();
+            for (i, entry) in SettingsEntry::ALL.iter().enumerate() {
+                let cursor = if i == settings_menu.selected {
+                    "> "
+                } else {
+                    ""
+                };
+                value += &format!("{}{}: {}\n", cursor, entry.label(), entry.value(&settings));
+            }
+            value += "Up/Down: Select, Left/Right: Change, TAB: Close";
+            text.sections[0].value = value;
+        }
+    }
+}
+
+/// Show tips the player hasn't seen yet in a timed banner.
+fn tip_system(
+    time: Res<Time>,
+    mut tip_events: EventReader<TipEvent>,
+    mut settings: ResMut<Settings>,
+    mut banner: ResMut<TipBanner>,
+    mut query: Query<(&mut Text, &mut Visibility), With<TipText>>,
+) {
+    for TipEvent(tip) in tip_events.iter() {
+        if settings.seen_tips.insert(*tip) {
+            settings.save();
+            banner.timer.reset();
+            for (mut text, _) in &mut query {
+                text.sections[0].value = tip.text().to_owned();
+            }
+        }
+    }
+    banner.timer.tick(time.delta());
+    for (_, mut visibility) in &mut query {
+        *visibility = if banner.timer.finished() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+fn last_breath_overlay_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    time_scale: Res<TimeScale>,
+    mut query: Query<&mut BackgroundColor, With<LastBreathOverlay>>,
+) {
+    // Pulse like a heartbeat, about once per real second.
+    let alpha = if time_scale.in_last_breath() && !settings.reduce_motion {
+        let beat = (time.raw_elapsed_seconds() * std::f32::consts::TAU).sin();
+        0.08 + 0.07 * beat
+    } else {
+        0.0
+    };
+    for mut color in &mut query {
+        color.0.set_a(alpha);
+    }
+}
+
+fn debug_overlay_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut debug_overlay: ResMut<DebugOverlay>,
+    latency: Res<InputLatency>,
+    step_debug: Res<StepDebug>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        debug_overlay.visible = !debug_overlay.visible;
+    }
+    let latency = match latency.last {
+        Some((duration, ticks)) => {
+            format!(
+                "{:.1} ms ({} ticks)",
+                duration.as_secs_f64() * 1000.0,
+                ticks
+            )
+        }
+        None => "press fire".to_owned(),
+    };
+    for (mut text, mut visibility) in &mut query {
+        *visibility = if debug_overlay.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if debug_overlay.visible {
+            text.sections[0].value =
+                format!("INPUT LATENCY: {}\nFRAME {}", latency, step_debug.frame);
+            if cfg!(debug_assertions) {
+                text.sections[0].value += if step_debug.paused {
+                    " (PAUSED, F5: Resume, F6: Step)"
+                } else {
+                    " (F5: Pause)"
+                };
+                text.sections[0].value += "\nF7: Collision lab (welcome screen)";
+            }
+        }
+    }
+}
+
+fn cell_inspector_system(
+    debug_overlay: Res<DebugOverlay>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    cell_query: Query<(&Transform, &Physics, &Cell)>,
+    mut tooltip_query: Query<(&mut Text, &mut Style, &mut Visibility), With<CellTooltip>>,
+) {
+    let Ok((mut text, mut style, mut visibility)) = tooltip_query.get_single_mut() else {
+        return;
+    };
+    *visibility = Visibility::Hidden;
+    if !debug_overlay.visible {
+        return;
+    }
+    let Some(cursor) = window_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+    else {
+        return;
+    };
+    let Some(point) = camera_query
+        .get_single()
+        .ok()
+        .and_then(|(camera, transform)| camera.viewport_to_world(transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    else {
+        return;
+    };
+    let hovered = cell_query
+        .iter()
+        .map(|(transform, physics, cell)| {
+            let distance = transform.translation.truncate().distance(point);
+            (distance, physics, cell)
+        })
+        .filter(|(distance, physics, _)| *distance <= physics.radius)
+        .min_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+    let Some((_, physics, cell)) = hovered else {
+        return;
+    };
+    let cell_type = match cell.cell_type {
+        CellType::Body { patient_hp } => format!("Blood cell (damage {})", patient_hp),
+        CellType::Germ => "Germ".to_owned(),
+        CellType::SplitterGerm => format!("Splitter germ (generation {})", cell.generation),
+        CellType::ShooterGerm { .. } => "Shooter germ".to_owned(),
+        CellType::ArmoredGerm { hits_remaining } => {
+            format!("Armored germ (armor {})", hits_remaining)
+        }
+    };
+    text.sections[0].value = format!(
+        "{}\nradius: {:.1}\ntarget radius: {:.1}\nvelocity: ({:.0}, {:.0})\nhealth: {:.0}%",
+        cell_type,
+        physics.radius,
+        cell.target_radius,
+        physics.velocity.x,
+        physics.velocity.y,
+        cell.health() * 100.0,
+    );
+    // The cursor position is measured from the bottom left corner.
+    style.position = UiRect {
+        left: Val::Px(cursor.x + 12.0),
+        bottom: Val::Px(cursor.y + 12.0),
+        ..default()
+    };
+    *visibility = Visibility::Inherited;
+}
+
+fn legend_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<&mut Visibility, With<LegendText>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::L) {
+        return;
+    }
+    for mut visibility in &mut query {
+        *visibility = if *visibility == Visibility::Hidden {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Highest score shown in the difficulty preview of the welcome screen.
+pub(crate) const DIFFICULTY_PREVIEW_MAX_SCORE: usize = 600;
+
+const DIFFICULTY_PREVIEW_STEP: usize = 50;
+
+/// Spawn a bar chart of `value(score)` for the scores in the difficulty preview.
+pub(crate) fn spawn_difficulty_chart(
+    builder: &mut ChildBuilder,
+    label: &str,
+    text_style: &TextStyle,
+    value: impl Fn(usize) -> f32,
+) {
+    let scores = (0..=DIFFICULTY_PREVIEW_MAX_SCORE).step_by(DIFFICULTY_PREVIEW_STEP);
+    let max_value = scores.clone().map(&value).fold(f32::EPSILON, f32::max);
+    let bar_count = scores.clone().count() as f32;
+    builder.spawn(TextBundle::from_section(label, text_style.clone()));
+    builder
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(260.0), Val::Px(60.0)),
+                align_items: AlignItems::End,
+                margin: UiRect::bottom(Val::Px(8.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.3).into(),
+            ..default()
+        })
+        .with_children(|builder| {
+            for score in scores {
+                builder.spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(
+                            Val::Percent(100.0 / bar_count),
+                            Val::Percent(100.0 * value(score) / max_value),
+                        ),
+                        border: UiRect::horizontal(Val::Px(1.0)),
+                        ..default()
+                    },
+                    background_color: NANO_COLOR.into(),
+                    ..default()
+                });
+            }
+        });
+}
+
+/// Keep the health bar above the boss at a constant size as the boss shrinks.
+#[allow(clippy::type_complexity)]
+fn boss_healthbar_system(
+    boss_query: Query<(&Transform, &Physics, &Cell), With<Boss>>,
+    mut bar_query: Query<(&Parent, &mut Transform), (With<BossHealthBar>, Without<Boss>)>,
+    mut fill_query: Query<
+        (&Parent, &mut Transform),
+        (With<BossHealthFill>, Without<BossHealthBar>, Without<Boss>),
+    >,
+) {
+    for (parent, mut transform) in &mut bar_query {
+        let Ok((boss_transform, physics, _)) = boss_query.get(parent.get()) else {
+            continue;
+        };
+        // The bar inherits the scale of the boss sprite, which is undone here.
+        let scale = boss_transform.scale.x.max(0.001);
+        transform.scale = Vec3::new(1.0 / scale, 1.0 / scale, 1.0);
+        transform.translation.y = (physics.radius + 16.0) / scale;
+    }
+    for (parent, mut transform) in &mut fill_query {
+        let Ok((bar_parent, _)) = bar_query.get(parent.get()) else {
+            continue;
+        };
+        if let Ok((_, _, cell)) = boss_query.get(bar_parent.get()) {
+            transform.scale.x = cell.health();
+        }
+    }
+}
+
+fn boss_banner_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    boss_fight: Res<BossFight>,
+    mut query: Query<&mut Visibility, With<BossBanner>>,
+) {
+    let visible = match &boss_fight.phase {
+        // Flash the banner, unless motion is reduced.
+        BossPhase::Warning(_) => {
+            settings.reduce_motion || (time.elapsed_seconds() * 4.0) as i32 % 2 == 0
+        }
+        _ => false,
+    };
+    for mut visibility in &mut query {
+        *visibility = if visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn chain_line_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Sprite, &mut ChainLine)>,
+) {
+    for (entity, mut sprite, mut line) in &mut query {
+        if line.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        } else {
+            sprite.color.set_a(line.alpha * line.timer.percent_left());
+        }
+    }
+}
+
+fn intercept_indicator_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    config: Res<GameConfig>,
+    boundaries: Res<Boundaries>,
+    marker_query: Query<Entity, With<InterceptMarker>>,
+    player_query: Query<&Transform, With<Player>>,
+    cell_query: Query<(&Transform, &Physics), With<Cell>>,
+) {
+    for entity in &marker_query {
+        commands.entity(entity).despawn();
+    }
+    if !settings.show_intercept {
+        return;
+    }
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let shooter = player_transform.translation.truncate();
+    for (transform, physics) in &cell_query {
+        if physics.velocity.y > -config.intercept_indicator_min_speed {
+            continue;
+        }
+        let Some(point) = intercept_point(
+            shooter,
+            config.bullet_speed,
+            transform.translation.truncate(),
+            physics.velocity,
+        ) else {
+            continue;
+        };
+        if point.y > boundaries.top || point.y < boundaries.bottom {
+            continue;
+        }
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 1.0, 0.6, 0.7),
+                    custom_size: Some(Vec2::splat(8.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(point.extend(3.0))
+                    .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                ..default()
+            },
+            InterceptMarker,
+        ));
+    }
+}
+
+fn hit_flash_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut query: Query<(&mut HitFlash, &mut Sprite)>,
+) {
+    let peak = if settings.reduce_motion { 1.2 } else { 1.8 };
+    for (mut hit_flash, mut sprite) in &mut query {
+        if hit_flash.timer.finished() {
+            continue;
+        }
+        hit_flash.timer.tick(time.delta());
+        // Colors above 1 brighten the texture. The alpha belongs to the spawn protection.
+        let strength = (peak - 1.0) * hit_flash.timer.percent_left();
+        let mut color = if hit_flash.harmful {
+            hit_flash.tint * (1.0 - strength.min(1.0)) + PENALTY_TEXT_COLOR * strength
+        } else {
+            hit_flash.tint * (1.0 + strength)
+        };
+        let alpha = sprite.color.a();
+        sprite.color = *color.set_a(alpha);
+    }
+}
+
+fn floating_text_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut query: Query<(Entity, &mut Transform, &mut Text, &mut FloatingText)>,
+) {
+    for (entity, mut transform, mut text, mut floating) in &mut query {
+        if floating.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if !settings.reduce_motion {
+            transform.translation.y += 40.0 * time.delta_seconds();
+        }
+        let alpha = floating.timer.percent_left();
+        for section in &mut text.sections {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intercept_point_with_simple_velocities() {
+        // The gap of 300 closes at 100 + 50 per second, so they meet after 2 seconds.
+        assert_eq!(
+            intercept_point(Vec2::ZERO, 100.0, vec2(0.0, 300.0), vec2(10.0, -50.0)),
+            Some(vec2(20.0, 200.0))
+        );
+        // A stationary cell is met at its own position.
+        assert_eq!(
+            intercept_point(Vec2::ZERO, 100.0, vec2(50.0, 300.0), Vec2::ZERO),
+            Some(vec2(50.0, 300.0))
+        );
+        // Cells rising faster than the bullet and cells below the shooter can't be hit.
+        assert_eq!(
+            intercept_point(Vec2::ZERO, 100.0, vec2(0.0, 300.0), vec2(0.0, 150.0)),
+            None
+        );
+        assert_eq!(
+            intercept_point(Vec2::ZERO, 100.0, vec2(0.0, -300.0), vec2(0.0, -50.0)),
+            None
+        );
+    }
+}